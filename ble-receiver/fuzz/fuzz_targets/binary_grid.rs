@@ -0,0 +1,18 @@
+//! cargo fuzz run binary_grid -- -malloc_limit_mb=64
+#![no_main]
+
+mod common;
+
+use ble_receiver::frame::{looks_like_binary_grid, parse_binary_grid, MAX_PAYLOAD};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, u8, [u8; 2], &[u8])| {
+    let (non_finite, out_of_range, unknown_pulse, data) = input;
+    let Some(grid) = parse_binary_grid(data) else {
+        return;
+    };
+    assert!(data.len() <= MAX_PAYLOAD && looks_like_binary_grid(data));
+    assert_eq!(data.len(), 5 + grid.cells.len() * grid.cells[0].len() * 2);
+
+    common::check_mapping(grid, non_finite, out_of_range, unknown_pulse);
+});
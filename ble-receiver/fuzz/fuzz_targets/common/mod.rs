@@ -0,0 +1,44 @@
+use ble_receiver::{
+    frame::{Grid, Units},
+    mapping::{CellPolicy, DepthRange, GridMapper, GridPolicy},
+    pattern::{PatternEngine, Slot},
+};
+use std::time::{Duration, Instant};
+
+const POLICIES: [CellPolicy; 5] = [
+    CellPolicy::Clamp,
+    CellPolicy::Far,
+    CellPolicy::Near,
+    CellPolicy::Hold,
+    CellPolicy::Unknown,
+];
+
+/// Runs a decoded grid through the worker's conversion and mapping and checks that only valid
+/// node states come out, whatever the cell policies.
+pub fn check_mapping(mut grid: Grid, non_finite: u8, out_of_range: u8, unknown_pulse: [u8; 2]) {
+    if grid.units == Units::Meters {
+        DepthRange::default().to_proximity(&mut grid.cells);
+        assert!(grid.cells.iter().flatten().all(|v| v.is_nan() || (0.0..=1.0).contains(v)));
+    }
+
+    let mut mapper = GridMapper::new(GridPolicy {
+        non_finite: POLICIES[non_finite as usize % POLICIES.len()],
+        out_of_range: POLICIES[out_of_range as usize % POLICIES.len()],
+        max_invalid_fraction: 1.0,
+        unknown_pulse,
+        ..Default::default()
+    });
+    let mapped = mapper.map(&grid.cells).expect("non-empty grid within the invalid limit");
+    assert!(mapped.values.iter().flatten().all(|v| (0.0..=1.0).contains(v)));
+
+    let states = mapper.states(&mapped.values);
+    let mut engine = PatternEngine::new(states);
+    let now = Instant::now();
+    if let Some(pulse) = mapper.unknown_pulse(&mapped.values) {
+        engine.play(Slot::Unknown, pulse, now);
+    }
+    let pulsed = engine.render(now + Duration::from_millis(mapper.policy.unknown_blink_ms));
+    for s in states.iter().chain(&pulsed) {
+        assert!((1..=4).contains(s), "{states:?} {pulsed:?}");
+    }
+}
@@ -0,0 +1,38 @@
+//! cargo fuzz run depth_frame -- -malloc_limit_mb=64
+#![no_main]
+
+use ble_receiver::{
+    depth::{decode, Binning, Reduce, MAX_DEPTH_PIXELS},
+    depth_ws::Processing,
+    worker::WorkerMsg,
+};
+use libfuzzer_sys::fuzz_target;
+use std::time::Instant;
+
+fuzz_target!(|input: (u8, u8, u8, bool, &[u8])| {
+    let (rows, cols, reduce, ground, data) = input;
+    let Ok(frame) = decode(data) else {
+        return;
+    };
+    assert!(frame.width * frame.height <= MAX_DEPTH_PIXELS);
+    assert_eq!(frame.meters.len(), frame.width * frame.height);
+
+    let mut processing = Processing {
+        ground: Default::default(),
+        drop_off: Default::default(),
+        binning: Binning {
+            rows: rows as usize % 16,
+            cols: cols as usize % 16,
+            reduce: [Reduce::Min, Reduce::Mean, Reduce::Median][reduce as usize % 3],
+            ..Default::default()
+        },
+        zone: Default::default(),
+    };
+    processing.ground.enabled = ground;
+
+    let WorkerMsg::Grid { grid, .. } = processing.to_msg(frame, Instant::now()) else {
+        panic!("depth frames become grids");
+    };
+    assert_eq!(grid.cells.len(), processing.binning.rows.max(1));
+    assert!(grid.cells.iter().all(|row| row.len() == processing.binning.cols.max(1)));
+});
@@ -0,0 +1,15 @@
+//! cargo fuzz run frame24 -- -malloc_limit_mb=64
+#![no_main]
+
+use ble_receiver::mux::{decode, unpack_frame24};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let cmds = unpack_frame24(data);
+    assert_eq!(cmds.is_some(), data.len() == 3);
+    if let Some(cmds) = cmds {
+        assert_eq!(decode(data), Some(cmds));
+        // 3-bit fields 0b011 and up fold to Hold; nothing else may light both pins.
+        assert!(cmds.iter().all(|c| c.to_state_bits() != (true, true)));
+    }
+});
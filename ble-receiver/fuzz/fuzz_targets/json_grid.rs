@@ -0,0 +1,18 @@
+//! cargo fuzz run json_grid -- -malloc_limit_mb=64
+#![no_main]
+
+mod common;
+
+use ble_receiver::frame::{is_rectangular, parse_json_grid, MAX_PAYLOAD};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, u8, [u8; 2], &[u8])| {
+    let (non_finite, out_of_range, unknown_pulse, data) = input;
+    let Some(grid) = parse_json_grid(data) else {
+        return;
+    };
+    assert!(data.len() <= MAX_PAYLOAD);
+    assert!(is_rectangular(&grid.cells) && !grid.cells[0].is_empty());
+
+    common::check_mapping(grid, non_finite, out_of_range, unknown_pulse);
+});
@@ -0,0 +1,26 @@
+//! cargo fuzz run raw_frame -- -malloc_limit_mb=64
+#![no_main]
+
+use ble_receiver::{
+    validation::{OutOfRange, RawError, RawPolicy},
+    NODE_COUNT,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u8, bool, &[u8])| {
+    let (levels, clamp, data) = input;
+    let policy = RawPolicy {
+        levels,
+        out_of_range: if clamp { OutOfRange::Clamp } else { OutOfRange::Reject },
+    };
+
+    match policy.check(data) {
+        Ok(checked) => {
+            assert_eq!(data.len(), NODE_COUNT);
+            assert!(checked.states.iter().all(|s| (1..=levels.max(1)).contains(s)), "{checked:?}");
+            assert!(clamp || checked.violations == 0);
+        }
+        Err(RawError::Length(len)) => assert_ne!(len, NODE_COUNT),
+        Err(RawError::OutOfRange { violations, .. }) => assert!(!clamp && violations > 0),
+    }
+});
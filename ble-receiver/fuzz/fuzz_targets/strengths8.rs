@@ -0,0 +1,14 @@
+//! cargo fuzz run strengths8 -- -malloc_limit_mb=64
+#![no_main]
+
+use ble_receiver::mux::{decode, strengths8_to_cmds};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let cmds = strengths8_to_cmds(data);
+    assert_eq!(cmds.is_some(), data.len() == 8);
+    if let Some(cmds) = cmds {
+        assert_eq!(decode(data), Some(cmds));
+        assert!(cmds.iter().all(|c| c.to_state_bits() != (true, true)));
+    }
+});
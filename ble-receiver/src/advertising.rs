@@ -0,0 +1,62 @@
+use crate::{config::Config, NODE_COUNT};
+use bluer::{adv::Advertisement, Adapter, Session, Uuid};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const BASE_NAME: &str = "WHV Haptic Receiver";
+
+/// Bluetooth SIG company ID reserved for testing; the phone filters on it together with the
+/// service UUID.
+pub const COMPANY_ID: u16 = 0xFFFF;
+pub const PROTOCOL_VERSION: u8 = 1;
+
+pub const STATUS_SERIAL_OK: u8 = 1 << 0;
+pub const STATUS_OWNED: u8 = 1 << 1;
+
+pub fn local_name(suffix: Option<&str>) -> String {
+    match suffix {
+        Some(s) if !s.is_empty() => format!("{BASE_NAME} {s}"),
+        _ => BASE_NAME.to_string(),
+    }
+}
+
+/// Manufacturer data layout: unit ID (u16 LE), protocol version, node count, status flags.
+pub fn manufacturer_data(unit_id: u16, status: u8) -> Vec<u8> {
+    let id = unit_id.to_le_bytes();
+    vec![id[0], id[1], PROTOCOL_VERSION, NODE_COUNT as u8, status]
+}
+
+pub fn build(config: &Config, service: Uuid, status: u8) -> Advertisement {
+    let mut service_uuids = BTreeSet::new();
+    service_uuids.insert(service);
+
+    let mut mfr = BTreeMap::new();
+    mfr.insert(COMPANY_ID, manufacturer_data(config.unit_id, status));
+
+    Advertisement {
+        service_uuids,
+        manufacturer_data: mfr,
+        discoverable: Some(true),
+        local_name: Some(local_name(config.name_suffix.as_deref())),
+        ..Default::default()
+    }
+}
+
+/// Picks the adapter named (`hci1`) or addressed (`DC:A6:32:..`) by `selector`, or the default
+/// adapter when none is given.
+pub async fn select_adapter(session: &Session, selector: Option<&str>) -> bluer::Result<Adapter> {
+    let Some(selector) = selector else {
+        return session.default_adapter().await;
+    };
+
+    for name in session.adapter_names().await? {
+        let adapter = session.adapter(&name)?;
+        if name == selector || adapter.address().await?.to_string().eq_ignore_ascii_case(selector) {
+            return Ok(adapter);
+        }
+    }
+
+    Err(bluer::Error {
+        kind: bluer::ErrorKind::NotFound,
+        message: format!("no Bluetooth adapter matches {selector:?}"),
+    })
+}
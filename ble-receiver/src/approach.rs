@@ -0,0 +1,114 @@
+//! Approach speed and time-to-collision per node, from consecutive timestamped frames. Something
+//! closing in fast is felt harder than its distance alone would give.
+
+use crate::{mapping::NodeValues, NODE_COUNT};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Frames closer together than this (a burst after a BLE hiccup) reuse the older reference
+/// rather than dividing by a near-zero interval.
+const MIN_SAMPLE_GAP: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ApproachConfig {
+    pub enabled: bool,
+    /// Weight of the newest speed sample, 0..=1; lower is smoother but slower to react.
+    pub smoothing: f32,
+    /// Frames further apart than this restart the estimate instead of giving one huge speed.
+    pub max_gap_ms: u64,
+    /// Approach speeds below this (m/s) are sensor noise, not something coming closer.
+    pub min_speed_mps: f32,
+    /// Nodes with a time-to-collision under this many seconds are boosted.
+    pub horizon_s: f32,
+    /// Proximity added at a time-to-collision of zero, shrinking linearly to nothing at the horizon.
+    pub max_boost: f32,
+}
+
+impl Default for ApproachConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            smoothing: 0.4,
+            max_gap_ms: 500,
+            min_speed_mps: 0.3,
+            horizon_s: 3.0,
+            max_boost: 0.5,
+        }
+    }
+}
+
+/// Per-node time-to-collision in seconds; `None` when nothing is approaching.
+pub type NodeTtc = [Option<f32>; NODE_COUNT];
+
+#[derive(Clone, Debug)]
+pub struct ApproachTracker {
+    pub config: ApproachConfig,
+    last: [Option<(f32, Instant)>; NODE_COUNT],
+    /// Smoothed approach speed in m/s, positive when closing in.
+    speed: [f32; NODE_COUNT],
+}
+
+impl ApproachTracker {
+    pub fn new(config: ApproachConfig) -> Self {
+        Self {
+            config,
+            last: [None; NODE_COUNT],
+            speed: [0.0; NODE_COUNT],
+        }
+    }
+
+    /// Feeds each node's depth in metres at `now`; unknown nodes restart their estimate.
+    pub fn update(&mut self, meters: &[Option<f32>; NODE_COUNT], now: Instant) -> NodeTtc {
+        let alpha = self.config.smoothing.clamp(0.0, 1.0);
+        let max_gap = Duration::from_millis(self.config.max_gap_ms);
+        let mut ttc = [None; NODE_COUNT];
+
+        for (idx, m) in meters.iter().enumerate() {
+            let Some(m) = m.filter(|m| m.is_finite()) else {
+                self.last[idx] = None;
+                self.speed[idx] = 0.0;
+                continue;
+            };
+
+            match self.last[idx] {
+                Some((_, t0)) if now.saturating_duration_since(t0) < MIN_SAMPLE_GAP => {}
+                Some((m0, t0)) if now - t0 <= max_gap => {
+                    let sample = (m0 - m) / (now - t0).as_secs_f32();
+                    self.speed[idx] = alpha * sample + (1.0 - alpha) * self.speed[idx];
+                    self.last[idx] = Some((m, now));
+                }
+                _ => {
+                    self.speed[idx] = 0.0;
+                    self.last[idx] = Some((m, now));
+                }
+            }
+            if self.speed[idx] >= self.config.min_speed_mps.max(f32::EPSILON) {
+                ttc[idx] = Some(m / self.speed[idx]);
+            }
+        }
+        ttc
+    }
+
+    /// Smoothed approach speeds in m/s, positive when closing in.
+    pub fn speeds(&self) -> [f32; NODE_COUNT] {
+        self.speed
+    }
+
+    /// `values` raised for nodes about to be reached; unknown nodes stay unknown.
+    pub fn boost(&self, values: &NodeValues, ttc: &NodeTtc) -> NodeValues {
+        let horizon = self.config.horizon_s;
+        if !self.config.enabled || horizon <= 0.0 {
+            return *values;
+        }
+        let mut out = *values;
+        for (v, t) in out.iter_mut().zip(ttc) {
+            if let (Some(v), Some(t)) = (v.as_mut(), t) {
+                if *t < horizon {
+                    *v = (*v + self.config.max_boost * (1.0 - t / horizon)).min(1.0);
+                }
+            }
+        }
+        out
+    }
+}
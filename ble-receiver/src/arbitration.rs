@@ -0,0 +1,97 @@
+use bluer::Address;
+use std::{collections::BTreeMap, time::Instant};
+
+#[derive(Clone, Debug)]
+pub struct Central {
+    pub first_seen: Instant,
+    pub last_seen: Instant,
+    pub frames: u64,
+    pub rejected: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteVerdict {
+    Accepted,
+    Rejected { owner: Address },
+}
+
+/// Tracks the centrals that talk to us and which one currently owns the belt.
+///
+/// The first central to write becomes the owner. Writes from anyone else are rejected
+/// until the owner releases control or disconnects (both end in `release`), or another
+/// central sends an explicit takeover command. A quiet owner keeps control.
+#[derive(Debug, Default)]
+pub struct Arbiter {
+    owner: Option<Address>,
+    centrals: BTreeMap<Address, Central>,
+}
+
+impl Arbiter {
+    pub fn owner(&self) -> Option<Address> {
+        self.owner
+    }
+
+    pub fn centrals(&self) -> &BTreeMap<Address, Central> {
+        &self.centrals
+    }
+
+    pub fn seen(&mut self, addr: Address, now: Instant) -> &mut Central {
+        let c = self.centrals.entry(addr).or_insert(Central {
+            first_seen: now,
+            last_seen: now,
+            frames: 0,
+            rejected: 0,
+        });
+        c.last_seen = now;
+        c
+    }
+
+    pub fn check_write(&mut self, addr: Address, now: Instant) -> WriteVerdict {
+        let verdict = self.check_command(addr, now);
+        if verdict == WriteVerdict::Accepted {
+            self.seen(addr, now).frames += 1;
+        }
+        verdict
+    }
+
+    /// The same rule for commands that change how the belt behaves (calibration, profiles,
+    /// patterns), without counting them as frames. Takeover and release are open to everyone.
+    pub fn check_command(&mut self, addr: Address, now: Instant) -> WriteVerdict {
+        match self.owner {
+            Some(owner) if owner != addr => {
+                self.seen(addr, now).rejected += 1;
+                WriteVerdict::Rejected { owner }
+            }
+            _ => {
+                self.owner = Some(addr);
+                self.seen(addr, now);
+                WriteVerdict::Accepted
+            }
+        }
+    }
+
+    /// Hands control to `addr` regardless of the current owner. Returns the previous owner.
+    pub fn takeover(&mut self, addr: Address, now: Instant) -> Option<Address> {
+        self.seen(addr, now);
+        let prev = self.owner.replace(addr);
+        prev.filter(|p| *p != addr)
+    }
+
+    /// Gives up control if `addr` is the owner. Returns whether anything changed.
+    pub fn release(&mut self, addr: Address) -> bool {
+        if self.owner == Some(addr) {
+            self.owner = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let owner = self
+            .owner
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| "none".to_string());
+        format!("owner={} centrals={}", owner, self.centrals.len())
+    }
+}
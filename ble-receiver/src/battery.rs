@@ -0,0 +1,33 @@
+use serde::Deserialize;
+use std::{io, path::PathBuf};
+
+/// Where the battery percentage comes from, e.g. `{"sysfs": "/sys/class/power_supply/BAT0"}`,
+/// `{"file": "/run/whv/battery"}` or `{"fixed": 80}`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatterySource {
+    /// A power-supply directory; its `capacity` attribute is read.
+    Sysfs(PathBuf),
+    /// A file containing a bare percentage.
+    File(PathBuf),
+    Fixed(u8),
+}
+
+impl BatterySource {
+    pub fn level(&self) -> io::Result<u8> {
+        let text = match self {
+            BatterySource::Sysfs(dir) => std::fs::read_to_string(dir.join("capacity"))?,
+            BatterySource::File(path) => std::fs::read_to_string(path)?,
+            BatterySource::Fixed(v) => return Ok((*v).min(100)),
+        };
+        parse_percent(&text)
+    }
+}
+
+fn parse_percent(text: &str) -> io::Result<u8> {
+    let v: i64 = text
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("bad battery level {text:?}: {e}")))?;
+    Ok(v.clamp(0, 100) as u8)
+}
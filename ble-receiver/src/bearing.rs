@@ -0,0 +1,135 @@
+//! Maps obstacles to nodes by bearing around the waist rather than by image column, so a phone
+//! pointed off to one side still reaches the nodes on that side of the belt.
+
+use crate::{
+    frame::View,
+    mapping::{NODE_COLS, NODE_ROWS},
+    NODE_COUNT,
+};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Bearings are remembered in bins this wide, all the way round.
+const BIN_DEG: f32 = 5.0;
+const BINS: usize = 72;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct Bearings {
+    /// Off: node `n` takes grid cell `n`, as it always has.
+    pub enabled: bool,
+    /// Centre of each node's sector, degrees clockwise from straight ahead of the torso.
+    pub node_bearing_deg: [f32; NODE_COUNT],
+    pub sector_width_deg: f32,
+    /// Horizontal field of view for grids that do not send their own.
+    pub fov_deg: f32,
+    /// Heading of the phone relative to the torso, e.g. for a mount off to one side.
+    pub yaw_offset_deg: f32,
+    /// Follow the camera heading from the pose, relative to the heading at calibration.
+    pub use_pose_yaw: bool,
+    /// Sectors out of view keep their last reading this long, so turning the phone or a second
+    /// sensor can cover more than one field of view.
+    pub memory_ms: u64,
+}
+
+impl Default for Bearings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_bearing_deg: [-40.0, 0.0, 40.0, -40.0, 0.0, 40.0],
+            sector_width_deg: 40.0,
+            fov_deg: 60.0,
+            yaw_offset_deg: 0.0,
+            use_pose_yaw: true,
+            memory_ms: 1500,
+        }
+    }
+}
+
+/// Last reading per bearing bin and node row.
+#[derive(Clone, Debug)]
+pub struct SectorMap {
+    pub config: Bearings,
+    /// Pose heading that counts as straight ahead; taken from the first frame with one.
+    reference_yaw: Option<f32>,
+    bins: [[Option<(f32, Instant)>; NODE_ROWS]; BINS],
+}
+
+impl SectorMap {
+    pub fn new(config: Bearings) -> Self {
+        Self {
+            config,
+            reference_yaw: None,
+            bins: [[None; NODE_ROWS]; BINS],
+        }
+    }
+
+    /// Makes the heading of the next frame with a pose straight ahead.
+    pub fn calibrate(&mut self) {
+        self.reference_yaw = None;
+    }
+
+    /// Bearing of the centre of `view` relative to the torso.
+    pub fn heading(&mut self, view: &View) -> f32 {
+        let pose = match view.yaw_deg {
+            Some(yaw) if self.config.use_pose_yaw => yaw - *self.reference_yaw.get_or_insert(yaw),
+            _ => 0.0,
+        };
+        wrap(self.config.yaw_offset_deg + pose)
+    }
+
+    /// Records the grid's columns at their bearings, grid rows split evenly over the node rows,
+    /// and returns each node's cell: the nearest fresh reading in its sector, `None` if none, so
+    /// a sector out of view follows `outside_grid` rather than reading as invalid.
+    /// Only cells in 0..=1 are recorded; a column with none clears what was seen there.
+    pub fn node_cells(&mut self, grid: &[Vec<f32>], view: &View, now: Instant) -> [Option<f32>; NODE_COUNT] {
+        let heading = self.heading(view);
+        let fov = view.fov_deg.unwrap_or(self.config.fov_deg).clamp(1.0, 360.0);
+        let (rows, cols) = (grid.len(), grid.first().map_or(0, Vec::len));
+
+        for c in 0..cols {
+            let width = fov / cols as f32;
+            let centre = heading - fov / 2.0 + (c as f32 + 0.5) * width;
+            for band in 0..NODE_ROWS {
+                let value = (0..rows)
+                    .filter(|r| r * NODE_ROWS / rows == band)
+                    .filter_map(|r| grid[r].get(c).copied())
+                    .filter(|v| (0.0..=1.0).contains(v))
+                    .reduce(f32::max);
+                for bin in bins_between(centre - width / 2.0, centre + width / 2.0) {
+                    self.bins[bin][band] = value.map(|v| (v, now));
+                }
+            }
+        }
+
+        let memory = Duration::from_millis(self.config.memory_ms);
+        let half = self.config.sector_width_deg / 2.0;
+        std::array::from_fn(|idx| {
+            let bearing = self.config.node_bearing_deg[idx];
+            bins_between(bearing - half, bearing + half)
+                .filter_map(|bin| self.bins[bin][idx / NODE_COLS])
+                .filter(|(_, seen)| now.saturating_duration_since(*seen) <= memory)
+                .map(|(v, _)| v)
+                .reduce(f32::max)
+        })
+    }
+}
+
+/// Into -180..180.
+fn wrap(deg: f32) -> f32 {
+    (deg + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Bins whose centre lies in `from..to`, or the one holding the midpoint when the span is
+/// narrower than a bin; never more than once round.
+fn bins_between(from: f32, to: f32) -> impl Iterator<Item = usize> {
+    let first = (from / BIN_DEG - 0.5).ceil() as i32;
+    let mut last = (to / BIN_DEG - 0.5).ceil() as i32;
+    let first = if last > first {
+        first
+    } else {
+        last = ((from + to) / 2.0 / BIN_DEG).floor() as i32 + 1;
+        last - 1
+    };
+    (first..last.min(first + BINS as i32)).map(|k| k.rem_euclid(BINS as i32) as usize)
+}
@@ -0,0 +1,129 @@
+//! Per-node corrections applied to every frame on its way to the Feather, for nodes that differ
+//! in tubing, bladder stiffness or where they sit on the body.
+//!
+//! Corrections work on levels, the inverse of node states: level 0 is state 4 (empty) and
+//! level 3 is state 1 (firmest).
+
+use crate::NODE_COUNT;
+use serde::Deserialize;
+use std::fmt;
+
+const MAX_LEVEL: u8 = 3;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NodeCalibration {
+    /// Levels are scaled by this, then rounded.
+    pub gain: f32,
+    /// Added to the level after `gain`.
+    pub offset: i8,
+    pub min_level: u8,
+    /// Caps a node that is uncomfortable or leaks at full pressure.
+    pub max_level: u8,
+    /// The physical output at this entry's index has its valve wired the other way round, so
+    /// every state sent to it is flipped (4 for 1), last. This is the output's polarity: it
+    /// holds whichever node drives the output, and when none does, so held-empty, disabled and
+    /// safe-state outputs are really empty.
+    pub invert: bool,
+    /// A broken node is held empty and drives no output.
+    pub disabled: bool,
+    /// Physical output this logical node drives; its own index when unset. Two enabled nodes
+    /// may not share one.
+    pub output: Option<usize>,
+}
+
+impl Default for NodeCalibration {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            offset: 0,
+            min_level: 0,
+            max_level: MAX_LEVEL,
+            invert: false,
+            disabled: false,
+            output: None,
+        }
+    }
+}
+
+impl NodeCalibration {
+    /// The node's corrected state, before the polarity of the output it lands on.
+    pub fn apply(&self, state: u8) -> u8 {
+        if self.disabled {
+            return 4;
+        }
+        let level = MAX_LEVEL - (state.clamp(1, 4) - 1);
+        let scaled = (f32::from(level) * self.gain).round() + f32::from(self.offset);
+        let max = self.max_level.min(MAX_LEVEL);
+        let level = scaled.clamp(f32::from(self.min_level.min(max)), f32::from(max)) as u8;
+        4 - level
+    }
+}
+
+/// One entry per logical node; the identity by default.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Calibration {
+    pub nodes: [NodeCalibration; NODE_COUNT],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalibrationError {
+    NoSuchOutput { node: usize, output: usize },
+    SharedOutput { output: usize, nodes: [usize; 2] },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::NoSuchOutput { node, output } => {
+                write!(f, "node {node} drives output {output}, outputs are 0..{NODE_COUNT}")
+            }
+            CalibrationError::SharedOutput { output, nodes: [a, b] } => {
+                write!(f, "nodes {a} and {b} both drive output {output}")
+            }
+        }
+    }
+}
+
+impl Calibration {
+    /// Logical node states to what each physical output is sent. Outputs no enabled node drives
+    /// are empty; every output is then flipped if its polarity is inverted. The safe state goes
+    /// through here too, so an inverted output is emptied rather than filled.
+    pub fn apply(&self, states: &[u8; NODE_COUNT]) -> [u8; NODE_COUNT] {
+        let mut out = [4; NODE_COUNT];
+        for (idx, (node, state)) in self.nodes.iter().zip(states).enumerate() {
+            if node.disabled {
+                continue;
+            }
+            if let Some(slot) = out.get_mut(node.output.unwrap_or(idx)) {
+                *slot = node.apply(*state);
+            }
+        }
+        for (slot, node) in out.iter_mut().zip(&self.nodes) {
+            if node.invert {
+                *slot = 5 - *slot;
+            }
+        }
+        out
+    }
+
+    /// Checks that every enabled node drives an output that exists and no other enabled node
+    /// drives. To swap two nodes live, disable one first.
+    pub fn validate(&self) -> Result<(), CalibrationError> {
+        let mut driven: [Option<usize>; NODE_COUNT] = [None; NODE_COUNT];
+        for (node, cal) in self.nodes.iter().enumerate().filter(|(_, c)| !c.disabled) {
+            let output = cal.output.unwrap_or(node);
+            let slot = driven
+                .get_mut(output)
+                .ok_or(CalibrationError::NoSuchOutput { node, output })?;
+            if let Some(first) = slot.replace(node) {
+                return Err(CalibrationError::SharedOutput {
+                    output,
+                    nodes: [first, node],
+                });
+            }
+        }
+        Ok(())
+    }
+}
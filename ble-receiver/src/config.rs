@@ -0,0 +1,156 @@
+use crate::{
+    approach::ApproachConfig,
+    battery::BatterySource,
+    bearing::Bearings,
+    calibration::Calibration,
+    danger::DangerZone,
+    depth::{Binning, GroundFilter},
+    dropoff::DropOff,
+    mapping::{DepthRange, GridPolicy},
+    validation::RawPolicy,
+    NODE_COUNT,
+};
+use serde::Deserialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+pub const CONFIG_ENV: &str = "WHV_CONFIG";
+pub const SERIAL_PATH: &str = "/dev/serial/by-id/usb-Adafruit_Feather_RP2040_DF648C86534125530-if00";
+
+/// Receiver settings, read from a JSON file. Every field is optional and falls back to
+/// the built-in default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Node states driven whenever the controlling phone goes away.
+    pub safe_state: [u8; NODE_COUNT],
+    /// Range and length checks for raw node-state frames.
+    pub raw: RawPolicy,
+    /// How NaN, missing and out-of-range grid cells reach the nodes.
+    pub grid: GridPolicy,
+    /// Near/far limits for grids sent in meters.
+    pub depth: DepthRange,
+    /// Nodes by bearing around the waist instead of by image column.
+    pub bearings: Bearings,
+    /// Listen for full depth frames over WebSocket here, e.g. `127.0.0.1:8765`; off when unset.
+    pub depth_ws_addr: Option<String>,
+    /// Which depth-frame pixels are floor rather than obstacles.
+    pub ground: GroundFilter,
+    /// How depth frames are reduced to a grid.
+    pub binning: Binning,
+    /// Obstacles straight ahead that stop the wearer.
+    pub danger: DangerZone,
+    /// Kerbs, stairs down and holes in depth frames.
+    pub drop_off: DropOff,
+    /// Time-to-collision estimates that boost fast-approaching obstacles.
+    pub approach: ApproachConfig,
+    /// Per-node corrections and output remapping, one entry per node.
+    pub calibration: Calibration,
+    /// JSON file of wearer profiles and which one is active; switching rewrites it.
+    pub profiles: Option<PathBuf>,
+    /// JSON file of named haptic patterns that can be played over BLE or with `--play`.
+    pub patterns: Option<PathBuf>,
+    /// Adapter name (`hci0`) or address; the default adapter when unset.
+    pub adapter: Option<String>,
+    /// Serial device of the Feather; point it at `whv-belt-sim`'s pty to run without hardware.
+    pub serial_path: String,
+    /// Appended to the advertised name so belts in the same room can be told apart.
+    pub name_suffix: Option<String>,
+    /// Sent in the advertisement's manufacturer data so the phone can pick this belt before
+    /// connecting, and used for the default serial number. The advertised name only changes with
+    /// `name_suffix`.
+    pub unit_id: u16,
+    /// Strings served by the Device Information Service.
+    pub device: DeviceInfo,
+    /// Battery Service is only exposed when a source is configured.
+    pub battery: Option<BatterySource>,
+    /// Report readiness and watchdog pings when started with `Type=notify`.
+    pub sd_notify: bool,
+    /// Attach per-frame fields (sequence, node states) when logging to journald.
+    pub journal_fields: bool,
+    /// Loopback address for the Prometheus text endpoint; `null` disables it. Other addresses
+    /// are refused, since the endpoint has no authentication.
+    pub metrics_addr: Option<String>,
+    /// Also write JSON log lines here; `$WHV_LOG_JSON` overrides it.
+    pub log_json: Option<PathBuf>,
+}
+
+/// Strings served by the Device Information Service.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DeviceInfo {
+    pub manufacturer: String,
+    pub model: String,
+    /// Defaults to `WHV-<unit_id as hex>`.
+    pub serial: Option<String>,
+}
+
+impl Default for DeviceInfo {
+    fn default() -> Self {
+        Self {
+            manufacturer: "Wearable Haptic Vision".to_string(),
+            model: "WHV Pi5 Receiver".to_string(),
+            serial: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            safe_state: [4; NODE_COUNT],
+            raw: RawPolicy::default(),
+            grid: GridPolicy::default(),
+            depth: DepthRange::default(),
+            bearings: Bearings::default(),
+            depth_ws_addr: None,
+            ground: GroundFilter::default(),
+            binning: Binning::default(),
+            danger: DangerZone::default(),
+            drop_off: DropOff::default(),
+            approach: ApproachConfig::default(),
+            calibration: Calibration::default(),
+            profiles: None,
+            patterns: None,
+            adapter: None,
+            serial_path: SERIAL_PATH.to_string(),
+            name_suffix: None,
+            unit_id: 0,
+            device: DeviceInfo::default(),
+            battery: None,
+            sd_notify: true,
+            journal_fields: true,
+            metrics_addr: Some("127.0.0.1:9464".to_string()),
+            log_json: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let config: Self = serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config
+            .calibration
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("calibration: {e}")))?;
+        Ok(config)
+    }
+
+    /// Loads the file named by `--config <path>` or `$WHV_CONFIG`, or the defaults if neither is set.
+    pub fn from_args() -> io::Result<Self> {
+        let mut args = std::env::args().skip(1);
+        let mut path = std::env::var_os(CONFIG_ENV).map(PathBuf::from);
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                path = args.next().map(PathBuf::from);
+            }
+        }
+        match path {
+            Some(p) => Self::load(&p),
+            None => Ok(Self::default()),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use bluer::{Adapter, AdapterEvent, Address, DeviceEvent, DeviceProperty};
+use futures::StreamExt;
+use std::{collections::BTreeMap, time::Instant};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkEvent {
+    Connected(Address),
+    Disconnected(Address),
+    Rssi(Address, i16),
+    Mtu(Address, u16),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Link {
+    pub connected: bool,
+    pub connected_at: Option<Instant>,
+    pub disconnected_at: Option<Instant>,
+    pub connects: u32,
+    pub disconnects: u32,
+    pub rssi: Option<i16>,
+    pub mtu: Option<u16>,
+}
+
+/// Per-central connection bookkeeping, fed by [`LinkEvent`]s.
+#[derive(Debug, Default)]
+pub struct Links {
+    links: BTreeMap<Address, Link>,
+}
+
+impl Links {
+    pub fn get(&self, addr: &Address) -> Option<&Link> {
+        self.links.get(addr)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Address, &Link)> {
+        self.links.iter()
+    }
+
+    pub fn connected_count(&self) -> usize {
+        self.links.values().filter(|l| l.connected).count()
+    }
+
+    /// Records `evt` and returns true if it ended a link that was up.
+    pub fn apply(&mut self, evt: LinkEvent, now: Instant) -> bool {
+        match evt {
+            LinkEvent::Connected(addr) => {
+                let l = self.links.entry(addr).or_default();
+                if !l.connected {
+                    l.connected = true;
+                    l.connected_at = Some(now);
+                    l.connects += 1;
+                }
+                false
+            }
+            LinkEvent::Disconnected(addr) => match self.links.get_mut(&addr) {
+                Some(l) if l.connected => {
+                    l.connected = false;
+                    l.disconnected_at = Some(now);
+                    l.disconnects += 1;
+                    true
+                }
+                _ => false,
+            },
+            LinkEvent::Rssi(addr, rssi) => {
+                self.links.entry(addr).or_default().rssi = Some(rssi);
+                false
+            }
+            LinkEvent::Mtu(addr, mtu) => {
+                self.links.entry(addr).or_default().mtu = Some(mtu);
+                false
+            }
+        }
+    }
+
+    pub fn status(&self) -> String {
+        format!("connected={}", self.connected_count())
+    }
+}
+
+/// Follows adapter and device property changes and reports them as [`LinkEvent`]s until the
+/// adapter event stream ends. A device that cannot be watched is logged and skipped.
+pub async fn watch_links(adapter: Adapter, tx: mpsc::UnboundedSender<LinkEvent>) -> bluer::Result<()> {
+    let mut events = adapter.events().await?;
+
+    for addr in adapter.device_addresses().await? {
+        watch_device(&adapter, addr, tx.clone());
+    }
+
+    while let Some(evt) = events.next().await {
+        match evt {
+            AdapterEvent::DeviceAdded(addr) => watch_device(&adapter, addr, tx.clone()),
+            AdapterEvent::DeviceRemoved(addr) => {
+                let _ = tx.send(LinkEvent::Disconnected(addr));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn watch_device(adapter: &Adapter, addr: Address, tx: mpsc::UnboundedSender<LinkEvent>) {
+    let device = match adapter.device(addr) {
+        Ok(device) => device,
+        Err(e) => {
+            warn!("Not tracking {addr}: {e}");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        if let Ok(true) = device.is_connected().await {
+            let _ = tx.send(LinkEvent::Connected(addr));
+            if let Ok(Some(rssi)) = device.rssi().await {
+                let _ = tx.send(LinkEvent::Rssi(addr, rssi));
+            }
+        }
+
+        let Ok(mut events) = device.events().await else {
+            return;
+        };
+
+        while let Some(DeviceEvent::PropertyChanged(prop)) = events.next().await {
+            let evt = match prop {
+                DeviceProperty::Connected(true) => LinkEvent::Connected(addr),
+                DeviceProperty::Connected(false) => LinkEvent::Disconnected(addr),
+                DeviceProperty::Rssi(rssi) => LinkEvent::Rssi(addr, rssi),
+                _ => continue,
+            };
+            if tx.send(evt).is_err() {
+                break;
+            }
+        }
+    });
+}
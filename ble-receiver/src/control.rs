@@ -0,0 +1,27 @@
+use crate::calibration::NodeCalibration;
+use serde::Deserialize;
+
+/// Commands written as JSON to the control characteristic, e.g. `{"cmd":"takeover"}`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    Takeover,
+    Release,
+    /// The wearer is facing where the phone points; bearings are measured from here.
+    Calibrate,
+    /// Plays a pattern from the pattern file over the live output, e.g. `{"cmd":"play","name":"turn_left"}`.
+    Play { name: String },
+    /// Ends a pattern started with `play`.
+    StopPattern,
+    /// Replaces one node's calibration until restart, e.g.
+    /// `{"cmd":"node_calibration","node":2,"max_level":2,"offset":-1}`; fields left out take their defaults.
+    NodeCalibration {
+        node: usize,
+        #[serde(flatten)]
+        calibration: NodeCalibration,
+    },
+}
+
+pub fn parse_command(bytes: &[u8]) -> Option<Command> {
+    serde_json::from_slice(bytes).ok()
+}
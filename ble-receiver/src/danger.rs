@@ -0,0 +1,167 @@
+//! Stop detection over the centre of each depth frame or metric grid, as `server.py` did:
+//! anything closer than `stop_m` straight ahead raises a stop that both the belt and the phone
+//! hear about.
+
+use crate::{
+    depth::DepthFrame,
+    frame::{Grid, Units},
+    mapping::DepthRange,
+    pattern::Pattern,
+    NODE_COUNT,
+};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// `min_m` reported when the crop has no valid depth; the value `server.py` used.
+pub const NO_DATA_M: f32 = 999.0;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct DangerZone {
+    pub enabled: bool,
+    /// Also check proximity grids, scaled back to metres with the depth range. Off by default:
+    /// the phone scales each legacy proximity grid to its own range, so the scaled-back distance
+    /// is not a real one and a near-looking cell would raise a false stop.
+    pub proximity_grids: bool,
+    /// Stop when the nearest point in the crop is closer than this.
+    pub stop_m: f32,
+    /// Clear once the nearest point is at least this far again, so noise does not flap the alert.
+    pub clear_m: f32,
+    /// Region checked, as fractions of width and height: `[left, top, right, bottom]`.
+    pub crop: [f32; 4],
+    /// While stopped all nodes double-tap: two taps of the first state, the second between.
+    pub alert_pulse: [u8; 2],
+    pub alert_blink_ms: u64,
+    /// Rest after each double tap.
+    pub alert_gap_ms: u64,
+}
+
+impl Default for DangerZone {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            proximity_grids: false,
+            stop_m: 0.8,
+            clear_m: 0.9,
+            crop: [1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0],
+            alert_pulse: [1, 4],
+            alert_blink_ms: 150,
+            alert_gap_ms: 450,
+        }
+    }
+}
+
+impl DangerZone {
+    /// The alert played while a stop is raised.
+    pub fn alert(&self) -> Pattern {
+        let all: Vec<usize> = (0..NODE_COUNT).collect();
+        Pattern::double_tap(&all, self.alert_pulse, self.alert_blink_ms, self.alert_gap_ms)
+    }
+
+    /// Nearest valid depth inside the crop of a full frame.
+    pub fn nearest_in_frame(&self, frame: &DepthFrame) -> Option<f32> {
+        let xs = span(frame.width, self.crop[0], self.crop[2]);
+        nearest(span(frame.height, self.crop[1], self.crop[3]).flat_map(|y| xs.clone().map(move |x| frame.at(x, y))))
+    }
+
+    /// Nearest depth inside the crop of a grid; coarse grids still get at least one cell.
+    /// Proximity grids have none unless `proximity_grids` is set, when they are scaled back to
+    /// metres with `depth`.
+    pub fn nearest_in_grid(&self, grid: &Grid, depth: &DepthRange) -> Option<f32> {
+        let rows = span(grid.cells.len(), self.crop[1], self.crop[3]);
+        let cells = grid.cells[rows]
+            .iter()
+            .flat_map(|row| row[span(row.len(), self.crop[0], self.crop[2])].iter().copied());
+        match grid.units {
+            Units::Meters => nearest(cells),
+            Units::Proximity if self.proximity_grids => nearest(cells.map(|p| depth.meters(p))),
+            Units::Proximity => None,
+        }
+    }
+}
+
+fn span(len: usize, lo: f32, hi: f32) -> Range<usize> {
+    if len == 0 {
+        return 0..0;
+    }
+    let start = ((lo.clamp(0.0, 1.0) * len as f32).floor() as usize).min(len - 1);
+    let end = ((hi.clamp(0.0, 1.0) * len as f32).ceil() as usize).clamp(start + 1, len);
+    start..end
+}
+
+fn nearest(depths: impl Iterator<Item = f32>) -> Option<f32> {
+    depths.filter(|m| m.is_finite() && *m > 0.0).reduce(f32::min)
+}
+
+/// What the phone is told after each frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct StopStatus {
+    pub stop: bool,
+    /// Nearest depth in the danger zone; `None` when it had no valid depth.
+    pub min_m: Option<f32>,
+    /// Distance ahead to a drop-off while its alert holds.
+    pub drop_m: Option<f32>,
+}
+
+impl StopStatus {
+    /// The reply `server.py` sent, e.g. `{"type":"cmd","stop":true,"min_m":0.62}`, plus
+    /// `"drop_off"` and, while it is set, `"drop_m"`.
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            r#"{{"type":"cmd","stop":{},"min_m":{:.2},"drop_off":{}"#,
+            self.stop,
+            self.min_m.unwrap_or(NO_DATA_M),
+            self.drop_m.is_some()
+        );
+        if let Some(m) = self.drop_m {
+            out.push_str(&format!(r#","drop_m":{m:.2}"#));
+        }
+        out.push('}');
+        out
+    }
+}
+
+/// Tracks whether a stop is raised, with hysteresis.
+#[derive(Clone, Debug)]
+pub struct StopDetector {
+    pub zone: DangerZone,
+    stopped: bool,
+}
+
+impl StopDetector {
+    pub fn new(zone: DangerZone) -> Self {
+        Self {
+            zone,
+            stopped: false,
+        }
+    }
+
+    /// Feeds the nearest depth of the latest frame; the flag says whether `stop` changed. No
+    /// valid depth clears the stop, as in `server.py`.
+    pub fn update(&mut self, min_m: Option<f32>) -> (StopStatus, bool) {
+        let was = self.stopped;
+        self.stopped = self.zone.enabled
+            && match min_m {
+                Some(m) if was => m < self.zone.clear_m,
+                Some(m) => m < self.zone.stop_m,
+                None => false,
+            };
+        (
+            StopStatus {
+                stop: self.stopped,
+                min_m,
+                drop_m: None,
+            },
+            self.stopped != was,
+        )
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Drops a raised stop; returns whether there was one.
+    pub fn reset(&mut self) -> bool {
+        std::mem::take(&mut self.stopped)
+    }
+}
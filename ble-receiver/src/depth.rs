@@ -0,0 +1,310 @@
+//! Full depth frames as sent to `server.py`: a little-endian u32 header length, a JSON
+//! header with intrinsics and pose, then a 16-bit grayscale PNG.
+
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, io::Cursor};
+
+/// Refuse images bigger than this before decoding them; LiDAR depth maps are 256x192.
+pub const MAX_DEPTH_PIXELS: usize = 1024 * 1024;
+const MAX_HEADER_LEN: usize = 16 * 1024;
+
+fn default_scale() -> f32 {
+    0.001
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DepthHeader {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+    /// Metres per PNG unit; millimetres unless the phone says otherwise.
+    #[serde(default = "default_scale")]
+    pub scale_m_per_unit: f32,
+    /// Camera-to-world transform, in ARKit's gravity-aligned world where +y is up.
+    pub pose_4x4_row_major: [f32; 16],
+}
+
+#[derive(Clone, Debug)]
+pub struct DepthFrame {
+    pub header: DepthHeader,
+    pub width: usize,
+    pub height: usize,
+    /// Row-major depth in metres; pixels the sensor reported as 0 are NaN.
+    pub meters: Vec<f32>,
+}
+
+impl DepthFrame {
+    pub fn at(&self, x: usize, y: usize) -> f32 {
+        self.meters[y * self.width + x]
+    }
+
+    /// World direction of pixel `(x, y)`, scaled so that one unit is one metre of depth.
+    /// Intrinsics are taken to be for the depth image, and the camera to look down its -z axis
+    /// with +y up, as ARKit's does.
+    pub fn ray(&self, x: usize, y: usize) -> [f32; 3] {
+        let h = &self.header;
+        let cam = [(x as f32 - h.cx) / h.fx, -(y as f32 - h.cy) / h.fy, -1.0];
+        let p = &h.pose_4x4_row_major;
+        [0, 1, 2].map(|r| p[r * 4] * cam[0] + p[r * 4 + 1] * cam[1] + p[r * 4 + 2] * cam[2])
+    }
+
+    /// Pixel `(x, y)` in world coordinates, or `None` without depth.
+    pub fn world_point(&self, x: usize, y: usize) -> Option<[f32; 3]> {
+        let d = self.at(x, y);
+        if !d.is_finite() {
+            return None;
+        }
+        let (o, dir) = (self.camera(), self.ray(x, y));
+        Some([0, 1, 2].map(|i| o[i] + d * dir[i]))
+    }
+
+    /// World position of the camera.
+    pub fn camera(&self) -> [f32; 3] {
+        let p = &self.header.pose_4x4_row_major;
+        [p[3], p[7], p[11]]
+    }
+
+    /// World height of the camera.
+    pub fn camera_y(&self) -> f32 {
+        self.camera()[1]
+    }
+
+    /// World heading the camera looks along, degrees clockwise seen from above.
+    pub fn yaw_deg(&self) -> f32 {
+        let p = &self.header.pose_4x4_row_major;
+        // Forward is the camera's -z axis: minus the third column of the rotation.
+        (-p[2]).atan2(p[10]).to_degrees()
+    }
+
+    /// Horizontal field of view from the intrinsics.
+    pub fn fov_deg(&self) -> f32 {
+        2.0 * (self.width as f32 / (2.0 * self.header.fx)).atan().to_degrees()
+    }
+}
+
+#[derive(Debug)]
+pub enum DepthError {
+    Truncated,
+    Header(serde_json::Error),
+    Png(png::DecodingError),
+    /// Not single-channel 16-bit, or too large.
+    Image(String),
+}
+
+impl fmt::Display for DepthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DepthError::Truncated => write!(f, "message shorter than its header length"),
+            DepthError::Header(e) => write!(f, "bad header: {e}"),
+            DepthError::Png(e) => write!(f, "bad PNG: {e}"),
+            DepthError::Image(e) => write!(f, "unsupported image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DepthError {}
+
+pub fn decode(msg: &[u8]) -> Result<DepthFrame, DepthError> {
+    let (len, rest) = msg.split_first_chunk::<4>().ok_or(DepthError::Truncated)?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if len > MAX_HEADER_LEN || len > rest.len() {
+        return Err(DepthError::Truncated);
+    }
+    let (header, png_bytes) = rest.split_at(len);
+    let header: DepthHeader = serde_json::from_slice(header).map_err(DepthError::Header)?;
+
+    let decoder = png::Decoder::new_with_limits(
+        Cursor::new(png_bytes),
+        png::Limits {
+            bytes: MAX_DEPTH_PIXELS * 2,
+        },
+    );
+    let mut reader = decoder.read_info().map_err(DepthError::Png)?;
+    let info = reader.info();
+    let (width, height) = (info.width as usize, info.height as usize);
+    if info.color_type != png::ColorType::Grayscale || info.bit_depth != png::BitDepth::Sixteen {
+        return Err(DepthError::Image(format!("{:?} {:?}", info.color_type, info.bit_depth)));
+    }
+    if width * height > MAX_DEPTH_PIXELS {
+        return Err(DepthError::Image(format!("{width}x{height}")));
+    }
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let out = reader.next_frame(&mut buf).map_err(DepthError::Png)?;
+    let scale = header.scale_m_per_unit;
+    let meters = buf[..out.buffer_size()]
+        .chunks_exact(2)
+        .map(|b| match u16::from_be_bytes([b[0], b[1]]) {
+            0 => f32::NAN,
+            v => v as f32 * scale,
+        })
+        .collect();
+
+    Ok(DepthFrame {
+        header,
+        width,
+        height,
+        meters,
+    })
+}
+
+/// Drops pixels on the floor, and anything above head height, before binning so a phone tilted
+/// down does not feel the ground as an obstacle.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct GroundFilter {
+    pub enabled: bool,
+    /// Height bands this thick are searched for the one holding the most points, which is
+    /// taken as the floor; the ground under the wearer outnumbers stairs further down.
+    pub floor_bin_m: f32,
+    /// Only points at least this far below the camera can be floor; without any, none is in
+    /// view and `camera_height_m` is used instead.
+    pub min_drop_m: f32,
+    /// Assumed height of the phone above the floor when the floor is not in view.
+    pub camera_height_m: f32,
+    /// Points this far above the floor and up to `max_height_m` are obstacles; the band
+    /// below covers floor noise, kerbs and carpet.
+    pub min_height_m: f32,
+    pub max_height_m: f32,
+}
+
+impl Default for GroundFilter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            floor_bin_m: 0.05,
+            min_drop_m: 0.5,
+            camera_height_m: 1.3,
+            min_height_m: 0.1,
+            max_height_m: 2.0,
+        }
+    }
+}
+
+impl GroundFilter {
+    /// World height of the floor under `frame`: the mean of the densest three adjacent height
+    /// bands, preferring the higher on a tie.
+    pub fn floor_y(&self, frame: &DepthFrame) -> f32 {
+        let bin = self.floor_bin_m.max(0.01);
+        let top = frame.camera_y() - self.min_drop_m;
+        let mut bins: BTreeMap<i32, (usize, f32)> = BTreeMap::new();
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                if let Some(h) = frame.world_point(x, y).map(|p| p[1]).filter(|h| h.is_finite() && *h <= top) {
+                    let e = bins.entry((h / bin).floor() as i32).or_default();
+                    e.0 += 1;
+                    e.1 += h;
+                }
+            }
+        }
+
+        let window = |k: i32| {
+            (k.saturating_sub(1)..=k.saturating_add(1))
+                .filter_map(|k| bins.get(&k))
+                .fold((0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1))
+        };
+        bins.keys()
+            .map(|k| window(*k))
+            .max_by_key(|(count, _)| *count)
+            .map(|(count, sum)| sum / count as f32)
+            .unwrap_or(frame.camera_y() - self.camera_height_m)
+    }
+
+    /// Sets pixels outside the obstacle band to no data; returns the floor height used.
+    pub fn apply(&self, frame: &mut DepthFrame) -> Option<f32> {
+        if !self.enabled {
+            return None;
+        }
+        let floor = self.floor_y(frame);
+        self.remove(frame, floor);
+        Some(floor)
+    }
+
+    /// Sets pixels outside the obstacle band above `floor` to no data.
+    pub fn remove(&self, frame: &mut DepthFrame, floor: f32) {
+        let band = floor + self.min_height_m..=floor + self.max_height_m;
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                if frame.world_point(x, y).is_some_and(|p| !band.contains(&p[1])) {
+                    frame.meters[y * frame.width + x] = f32::NAN;
+                }
+            }
+        }
+    }
+}
+
+/// How a bin's pixels become one cell.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reduce {
+    /// Closest pixel; what the phone sends today.
+    #[default]
+    Min,
+    Mean,
+    Median,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct Binning {
+    pub rows: usize,
+    pub cols: usize,
+    pub reduce: Reduce,
+    /// Bins with fewer valid pixels than this share are no data.
+    pub min_valid_fraction: f32,
+}
+
+impl Default for Binning {
+    fn default() -> Self {
+        Self {
+            rows: 2,
+            cols: 3,
+            reduce: Reduce::Min,
+            min_valid_fraction: 0.1,
+        }
+    }
+}
+
+impl Binning {
+    /// Splits the image into `rows x cols` equal bins and reduces each to metres, NaN for no data.
+    pub fn apply(&self, frame: &DepthFrame) -> Vec<Vec<f32>> {
+        let (rows, cols) = (self.rows.max(1), self.cols.max(1));
+        let mut pixels = Vec::new();
+
+        (0..rows)
+            .map(|r| {
+                (0..cols)
+                    .map(|c| {
+                        let ys = r * frame.height / rows..(r + 1) * frame.height / rows;
+                        let xs = c * frame.width / cols..(c + 1) * frame.width / cols;
+                        let total = ys.len() * xs.len();
+
+                        pixels.clear();
+                        for y in ys {
+                            pixels.extend(xs.clone().map(|x| frame.at(x, y)).filter(|v| v.is_finite()));
+                        }
+                        if total == 0 || (pixels.len() as f32) < self.min_valid_fraction * total as f32 {
+                            return f32::NAN;
+                        }
+                        self.reduce(&mut pixels)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn reduce(&self, pixels: &mut [f32]) -> f32 {
+        if pixels.is_empty() {
+            return f32::NAN;
+        }
+        match self.reduce {
+            Reduce::Min => pixels.iter().copied().fold(f32::INFINITY, f32::min),
+            Reduce::Mean => pixels.iter().sum::<f32>() / pixels.len() as f32,
+            Reduce::Median => {
+                let mid = pixels.len() / 2;
+                *pixels.select_nth_unstable_by(mid, f32::total_cmp).1
+            }
+        }
+    }
+}
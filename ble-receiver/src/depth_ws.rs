@@ -0,0 +1,179 @@
+use crate::{
+    danger::{DangerZone, StopStatus},
+    depth::{self, Binning, DepthFrame, GroundFilter},
+    dropoff::DropOff,
+    frame::{Grid, Units, View},
+    metrics::{FrameFormat, Metrics, ParseFailure},
+    state::AppState,
+    worker::WorkerMsg,
+};
+use bluer::Address;
+use futures::{SinkExt, StreamExt};
+use std::{io, sync::Arc, time::Instant};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch, Mutex},
+};
+use tokio_tungstenite::tungstenite::{protocol::WebSocketConfig, Message};
+use tracing::{debug, info, warn};
+
+const MAX_MESSAGE: usize = 4 * 1024 * 1024;
+
+/// What happens to each depth frame before the worker sees it.
+#[derive(Clone, Copy, Debug)]
+pub struct Processing {
+    pub ground: GroundFilter,
+    pub drop_off: DropOff,
+    pub binning: Binning,
+    pub zone: DangerZone,
+}
+
+impl Processing {
+    /// Looks for drop-offs, removes the floor, then bins the frame and finds the nearest point
+    /// in the danger zone.
+    pub fn to_msg(&self, mut frame: DepthFrame, received: Instant) -> WorkerMsg {
+        let floor = self.ground.floor_y(&frame);
+        let drop_off = self.drop_off.check(&frame, floor);
+        if self.ground.enabled {
+            self.ground.remove(&mut frame, floor);
+            debug!(floor_y = floor, "Removed ground plane");
+        }
+        WorkerMsg::Grid {
+            format: FrameFormat::Depth,
+            grid: Grid {
+                cells: self.binning.apply(&frame),
+                units: Units::Meters,
+                view: View {
+                    yaw_deg: Some(frame.yaw_deg()),
+                    fov_deg: Some(frame.fov_deg()),
+                },
+            },
+            nearest_m: self.zone.nearest_in_frame(&frame),
+            drop_off,
+            received,
+        }
+    }
+}
+
+/// Whether a depth frame may drive the belt right now.
+enum Gate {
+    Open,
+    /// A BLE central holds control; WebSocket frames wait until it releases.
+    Owned(Address),
+    ShuttingDown,
+}
+
+async fn gate(state: &Mutex<AppState>) -> Gate {
+    let st = state.lock().await;
+    match st.arbiter.owner() {
+        _ if st.shutting_down => Gate::ShuttingDown,
+        Some(owner) => Gate::Owned(owner),
+        None => Gate::Open,
+    }
+}
+
+/// Accepts depth frames over WebSocket, as `server.py` did, and hands the binned grid to the
+/// worker. Frames are only taken while no BLE central holds control, and decoding and binning
+/// run on the blocking pool so a large frame cannot stall the worker. Every client gets the
+/// `{"type":"cmd",...}` reply for each frame the worker handles.
+pub async fn serve(
+    addr: &str,
+    processing: Processing,
+    state: Arc<Mutex<AppState>>,
+    tx: mpsc::UnboundedSender<WorkerMsg>,
+    stops: watch::Receiver<StopStatus>,
+    metrics: Arc<Metrics>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Depth frames on ws://{}", listener.local_addr()?);
+
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE),
+        max_frame_size: Some(MAX_MESSAGE),
+        ..Default::default()
+    };
+
+    loop {
+        let (sock, peer) = listener.accept().await?;
+        let tx = tx.clone();
+        let state = Arc::clone(&state);
+        let metrics = Arc::clone(&metrics);
+        let mut stops = stops.clone();
+        tokio::spawn(async move {
+            let mut ws = match tokio_tungstenite::accept_async_with_config(sock, Some(config)).await {
+                Ok(ws) => ws,
+                Err(e) => {
+                    warn!("WebSocket handshake with {peer} failed: {e}");
+                    return;
+                }
+            };
+            info!("Depth client {peer} connected");
+            stops.mark_unchanged();
+
+            loop {
+                let msg = tokio::select! {
+                    msg = ws.next() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                    changed = stops.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        let reply = stops.borrow_and_update().to_json();
+                        if let Err(e) = ws.send(Message::Text(reply)).await {
+                            warn!("Reply to depth client {peer} failed: {e}");
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                match msg {
+                    Ok(Message::Binary(data)) => {
+                        let received = Instant::now();
+                        match gate(&state).await {
+                            Gate::Open => {}
+                            Gate::Owned(owner) => {
+                                debug!("Ignoring depth frame from {peer}; control is owned by {owner}");
+                                continue;
+                            }
+                            Gate::ShuttingDown => break,
+                        }
+                        let decoded = tokio::task::spawn_blocking(move || {
+                            depth::decode(&data).map(|frame| processing.to_msg(frame, received))
+                        })
+                        .await;
+                        match decoded {
+                            // Control may have been taken while the frame was being binned.
+                            Ok(Ok(msg)) => match gate(&state).await {
+                                Gate::Open => {
+                                    if tx.send(msg).is_err() {
+                                        break;
+                                    }
+                                }
+                                Gate::Owned(_) => {}
+                                Gate::ShuttingDown => break,
+                            },
+                            Ok(Err(e)) => {
+                                metrics.parse_failure(ParseFailure::Depth);
+                                warn!("Dropping depth frame from {peer}: {e}");
+                            }
+                            Err(e) => {
+                                warn!("Depth frame from {peer} could not be processed: {e}");
+                                break;
+                            }
+                        }
+                    }
+                    Ok(Message::Text(text)) => debug!("Text from {peer}: {text}"),
+                    Ok(Message::Close(_)) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("Depth client {peer} failed: {e}");
+                        break;
+                    }
+                }
+            }
+            info!("Depth client {peer} disconnected");
+        });
+    }
+}
@@ -0,0 +1,165 @@
+//! Negative obstacles: kerbs, stairs going down and holes. Where the floor should be visible a
+//! little way ahead, the depth frame instead sees nothing or something well below it.
+
+use crate::{depth::DepthFrame, pattern::Pattern, NODE_COUNT};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct DropOff {
+    pub enabled: bool,
+    /// Ground at least this far below the floor is a drop.
+    pub min_drop_m: f32,
+    /// Only floor expected within this horizontal distance is checked.
+    pub lookahead_m: f32,
+    /// The walkway checked, as fractions of the image width: `[left, right]`.
+    pub walkway: [f32; 2],
+    /// Share of the expected floor that has to measure too low to raise the hazard.
+    pub min_fraction: f32,
+    /// Share of the expected floor that has to have no depth at all to raise the hazard. Higher
+    /// than `min_fraction`, since dark, shiny or sunlit floor also reads as no depth.
+    pub min_missing_fraction: f32,
+    /// With fewer expected floor pixels the phone is not looking at the ground; no check.
+    pub min_pixels: usize,
+    /// Keeps the alert going this long after the last frame that saw the drop.
+    pub hold_ms: u64,
+    /// Node states the alert cycles through; by default the rows take turns, top then bottom.
+    pub alert_frames: [[u8; NODE_COUNT]; 2],
+    pub alert_blink_ms: u64,
+}
+
+impl Default for DropOff {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_drop_m: 0.15,
+            lookahead_m: 2.0,
+            walkway: [1.0 / 3.0, 2.0 / 3.0],
+            min_fraction: 0.25,
+            min_missing_fraction: 0.6,
+            min_pixels: 20,
+            hold_ms: 1000,
+            alert_frames: [[1, 1, 1, 4, 4, 4], [4, 4, 4, 1, 1, 1]],
+            alert_blink_ms: 250,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct DropOffHit {
+    /// Share of the expected floor that measured below it.
+    pub fraction: f32,
+    /// Share of the expected floor with no depth.
+    pub missing: f32,
+    /// Horizontal distance to the nearest point counted towards the share that raised the hit.
+    pub distance_m: f32,
+}
+
+impl DropOff {
+    /// The alert played while a drop-off holds.
+    pub fn alert(&self) -> Pattern {
+        Pattern::frames(&self.alert_frames, self.alert_blink_ms, 0)
+    }
+
+    /// Casts each walkway pixel onto the floor at `floor_y`; pixels whose floor point lies within
+    /// the lookahead but that measured depth below the floor, or no depth, count towards a drop.
+    /// The two are counted apart and each has its own threshold.
+    pub fn check(&self, frame: &DepthFrame, floor_y: f32) -> Option<DropOffHit> {
+        if !self.enabled || frame.width == 0 {
+            return None;
+        }
+        let camera_y = frame.camera_y();
+        let left = (self.walkway[0].clamp(0.0, 1.0) * frame.width as f32) as usize;
+        let right = ((self.walkway[1].clamp(0.0, 1.0) * frame.width as f32).ceil() as usize).clamp(left, frame.width);
+
+        let (mut expected, mut low, mut missing) = (0usize, 0usize, 0usize);
+        let (mut nearest_low, mut nearest_missing) = (f32::INFINITY, f32::INFINITY);
+        for y in 0..frame.height {
+            for x in left..right {
+                let dir = frame.ray(x, y);
+                if dir[1] >= 0.0 {
+                    continue;
+                }
+                let t = (floor_y - camera_y) / dir[1];
+                let ahead = t * dir[0].hypot(dir[2]);
+                if !(0.0..=self.lookahead_m).contains(&ahead) {
+                    continue;
+                }
+                expected += 1;
+
+                let d = frame.at(x, y);
+                if !d.is_finite() {
+                    missing += 1;
+                    nearest_missing = nearest_missing.min(ahead);
+                } else if camera_y + d * dir[1] < floor_y - self.min_drop_m {
+                    low += 1;
+                    nearest_low = nearest_low.min(ahead);
+                }
+            }
+        }
+        if expected < self.min_pixels.max(1) {
+            return None;
+        }
+
+        let fraction = low as f32 / expected as f32;
+        let missing = missing as f32 / expected as f32;
+        let low_hit = fraction >= self.min_fraction;
+        let missing_hit = missing >= self.min_missing_fraction;
+        let distance_m = match (low_hit, missing_hit) {
+            (true, true) => nearest_low.min(nearest_missing),
+            (true, false) => nearest_low,
+            (false, true) => nearest_missing,
+            (false, false) => return None,
+        };
+        Some(DropOffHit {
+            fraction,
+            missing,
+            distance_m,
+        })
+    }
+}
+
+/// Holds a drop-off alert for a while after it was last seen.
+#[derive(Clone, Debug)]
+pub struct DropOffAlert {
+    pub config: DropOff,
+    last: Option<(DropOffHit, Instant)>,
+}
+
+impl DropOffAlert {
+    pub fn new(config: DropOff) -> Self {
+        Self {
+            config,
+            last: None,
+        }
+    }
+
+    /// Records the latest frame's result; true when this raises a new alert.
+    pub fn update(&mut self, hit: Option<DropOffHit>, now: Instant) -> bool {
+        let was = self.is_active(now);
+        if let Some(hit) = hit {
+            self.last = Some((hit, now));
+        }
+        !was && self.is_active(now)
+    }
+
+    /// The last hit, while the alert holds.
+    pub fn hit(&self, now: Instant) -> Option<DropOffHit> {
+        let hold = Duration::from_millis(self.config.hold_ms);
+        self.last
+            .filter(|(_, seen)| now.saturating_duration_since(*seen) <= hold)
+            .map(|(hit, _)| hit)
+    }
+
+    pub fn is_active(&self, now: Instant) -> bool {
+        self.hit(now).is_some()
+    }
+
+    /// Drops a held alert; returns whether there was one.
+    pub fn reset(&mut self, now: Instant) -> bool {
+        let was = self.is_active(now);
+        self.last = None;
+        was
+    }
+}
@@ -0,0 +1,158 @@
+use serde::Deserialize;
+
+/// Largest attribute value ATT allows; nothing longer can arrive over one BLE write, so
+/// decoders refuse it rather than allocating for it.
+pub const MAX_PAYLOAD: usize = 512;
+
+/// Byte-per-byte hex, as logged at debug level for every write.
+pub fn hex_dump(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ")
+}
+
+/// Header of the binary grid format: magic, units, rows, cols.
+pub const BINARY_GRID_MAGIC: [u8; 2] = *b"WG";
+const BINARY_HEADER_LEN: usize = 5;
+/// Binary cell meaning "no data".
+pub const BINARY_NO_DATA: u16 = 0xFFFF;
+/// Binary proximity cells count in 1/10000ths; meters cells in millimetres.
+const BINARY_PROXIMITY_SCALE: f32 = 10_000.0;
+const BINARY_MM_PER_M: f32 = 1_000.0;
+
+/// What the cells of a grid measure.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// 0.0 (far) to 1.0 (near), already scaled by the phone.
+    #[default]
+    Proximity,
+    /// Distance in metres, scaled on the Pi with the configured near/far limits.
+    Meters,
+}
+
+impl Units {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Units::Proximity => "proximity",
+            Units::Meters => "meters",
+        }
+    }
+}
+
+/// Where the camera pointed for a grid, when the sender knows.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct View {
+    /// World heading of the camera in degrees, clockwise seen from above.
+    pub yaw_deg: Option<f32>,
+    /// Horizontal field of view spanned by the grid's columns.
+    pub fov_deg: Option<f32>,
+}
+
+/// A decoded grid; missing cells are NaN.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Grid {
+    pub cells: Vec<Vec<f32>>,
+    pub units: Units,
+    pub view: View,
+}
+
+/// `parse_json_grid` or `parse_binary_grid`.
+pub type GridDecoder = fn(&[u8]) -> Option<Grid>;
+
+/// JSON payloads start with `{` or `[`; anything else is treated as raw node states.
+pub fn looks_like_json(data: &[u8]) -> bool {
+    matches!(data.first(), Some(b'{' | b'['))
+}
+
+pub fn looks_like_binary_grid(data: &[u8]) -> bool {
+    data.starts_with(&BINARY_GRID_MAGIC)
+}
+
+/// Accepts a bare `[[..], ..]` array of proximities or `{"grid": [[..], ..], "units": ..}`,
+/// optionally with `"yaw_deg"` and `"fov_deg"`. `null` cells mean "no data" and come back as
+/// NaN; grids with no cells are refused.
+pub fn parse_json_grid(bytes: &[u8]) -> Option<Grid> {
+    if bytes.len() > MAX_PAYLOAD {
+        return None;
+    }
+
+    type Cells = Vec<Vec<Option<f32>>>;
+
+    #[derive(Deserialize)]
+    struct Obj {
+        grid: Cells,
+        #[serde(default)]
+        units: Units,
+        yaw_deg: Option<f32>,
+        fov_deg: Option<f32>,
+    }
+
+    let obj = serde_json::from_slice::<Cells>(bytes)
+        .map(|grid| Obj {
+            grid,
+            units: Units::Proximity,
+            yaw_deg: None,
+            fov_deg: None,
+        })
+        .or_else(|_| serde_json::from_slice::<Obj>(bytes))
+        .ok()?;
+    let cells: Vec<Vec<f32>> = obj
+        .grid
+        .into_iter()
+        .map(|row| row.into_iter().map(|c| c.unwrap_or(f32::NAN)).collect())
+        .collect();
+
+    (is_rectangular(&cells) && cells.first().is_some_and(|r| !r.is_empty())).then_some(Grid {
+        cells,
+        units: obj.units,
+        view: View {
+            yaw_deg: obj.yaw_deg,
+            fov_deg: obj.fov_deg,
+        },
+    })
+}
+
+/// `"WG"`, units (0 proximity, 1 meters), rows, cols, then `rows * cols` little-endian u16
+/// cells row by row. Proximity is in 1/10000ths, meters in millimetres, 0xFFFF is no data.
+pub fn parse_binary_grid(bytes: &[u8]) -> Option<Grid> {
+    if bytes.len() > MAX_PAYLOAD {
+        return None;
+    }
+    let (header, body) = bytes.split_at_checked(BINARY_HEADER_LEN)?;
+    if header[..2] != BINARY_GRID_MAGIC {
+        return None;
+    }
+    let (units, scale) = match header[2] {
+        0 => (Units::Proximity, BINARY_PROXIMITY_SCALE),
+        1 => (Units::Meters, BINARY_MM_PER_M),
+        _ => return None,
+    };
+    let (rows, cols) = (header[3] as usize, header[4] as usize);
+    if rows == 0 || cols == 0 || body.len() != rows * cols * 2 {
+        return None;
+    }
+
+    let cells = body
+        .chunks_exact(cols * 2)
+        .map(|row| {
+            row.chunks_exact(2)
+                .map(|c| match u16::from_le_bytes([c[0], c[1]]) {
+                    BINARY_NO_DATA => f32::NAN,
+                    v => v as f32 / scale,
+                })
+                .collect()
+        })
+        .collect();
+    Some(Grid {
+        cells,
+        units,
+        ..Default::default()
+    })
+}
+
+pub fn is_rectangular(v: &[Vec<f32>]) -> bool {
+    if v.is_empty() {
+        return true;
+    }
+    let cols = v[0].len();
+    v.iter().all(|r| r.len() == cols)
+}
@@ -0,0 +1,33 @@
+pub mod advertising;
+pub mod approach;
+pub mod arbitration;
+pub mod battery;
+pub mod bearing;
+pub mod calibration;
+pub mod config;
+pub mod connection;
+pub mod control;
+pub mod danger;
+pub mod depth;
+#[cfg(feature = "ws")]
+pub mod depth_ws;
+pub mod dropoff;
+pub mod frame;
+pub mod logging;
+pub mod mapping;
+pub mod metrics;
+pub mod mux;
+pub mod pattern;
+pub mod profile;
+pub mod shutdown;
+pub mod sim;
+pub mod standard_services;
+pub mod state;
+pub mod systemd;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod validation;
+pub mod worker;
+
+/// Number of inflatable nodes driven by the Feather (see `hardware/code.py`).
+pub const NODE_COUNT: usize = 6;
@@ -0,0 +1,43 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+pub const LOG_JSON_ENV: &str = "WHV_LOG_JSON";
+
+/// `$WHV_LOG_JSON` if set, otherwise the configured path.
+pub fn json_path(configured: Option<&Path>) -> Option<PathBuf> {
+    std::env::var_os(LOG_JSON_ENV)
+        .map(PathBuf::from)
+        .or_else(|| configured.map(Path::to_path_buf))
+}
+
+/// Human-readable output on stderr (unless `human` is false, e.g. while the TUI owns the
+/// terminal) and, when `json` is given, one JSON object per event appended to that file.
+/// `RUST_LOG` sets the level for both (default `info`); per-byte RX dumps are `debug`.
+pub fn init(json: Option<&Path>, human: bool) -> io::Result<()> {
+    let filter = || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let human = human.then(|| fmt::layer().with_writer(io::stderr).with_filter(filter()));
+
+    let json = match json {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true)
+                    .with_writer(Mutex::new(file))
+                    .with_filter(filter()),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry().with(human).with(json).init();
+    Ok(())
+}
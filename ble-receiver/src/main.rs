@@ -1,13 +1,19 @@
-use bluer::{adv::Advertisement, gatt::local::{Application, Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, Service}, Uuid};
+use ble_receiver::{logging, shutdown::{self, EXIT_OK, EXIT_SAFE_STATE_FAILED, UNREGISTER_GRACE}};
+use bluer::{adv::Advertisement, gatt::local::{Application, Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, ReqError, Service}, Uuid};
 use futures::FutureExt;
-use std::{collections::{BTreeSet, VecDeque}, io::Write as _, sync::Arc, time::Duration};
-use tokio::{sync::Mutex, time::sleep};
+use std::{collections::{BTreeSet, VecDeque}, io::Write as _, process::ExitCode, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc}, time::Duration};
+use tokio::sync::Mutex;
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
 
 const SRV_UUID: Uuid = Uuid::from_u128(0x8b322909_2d3b_447b_a4d5_dfe0c009ec5a);
 const WR_CHAR_UUID: Uuid = Uuid::from_u128(0x8b32290a_2d3b_447b_a4d5_dfe0c009ec5a);
 const INFO_UUID: Uuid = Uuid::from_u128(0x8b32290c_2d3b_447b_a4d5_dfe0c009ec5a);
 const SERIAL_PATH: &str = "/dev/serial/by-id/usb-Adafruit_Feather_RP2040_DF648C86534125530-if00";
 const HISTORY_MAX: usize = 8;
+const SAFE_STATE: [u8; 6] = [4; 6];
+static FRAME_SEQ: AtomicU64 = AtomicU64::new(0);
+/// Held for each write to the Feather so the safe state cannot be overtaken by a frame in flight.
+static SERIAL_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
 #[derive(Clone, Debug)]
 struct GridFrame { rows: usize, cols: usize, data: Vec<Vec<f32>> }
@@ -16,16 +22,18 @@ struct GridFrame { rows: usize, cols: usize, data: Vec<Vec<f32>> }
 struct AppState { last_raw: Vec<u8>, last_grid: Option<GridFrame>, history: VecDeque<GridFrame> }
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
-    env_logger::init();
+async fn main() -> ExitCode {
+    logging::init(logging::json_path(None).as_deref(), true).expect("open JSON log");
     let session = bluer::Session::new().await.expect("create bluer session");
     let adapter = session.default_adapter().await.expect("get default adapter");
     adapter.set_powered(true).await.expect("power on adapter");
     let mut svc = BTreeSet::new();
     svc.insert(SRV_UUID);
     let adv = Advertisement { service_uuids: svc, discoverable: Some(true), local_name: Some("WHV Haptic Receiver".to_string()), ..Default::default() };
-    let _adv_handle = adapter.advertise(adv).await.expect("start advertising");
+    let adv_handle = adapter.advertise(adv).await.expect("start advertising");
     let state = Arc::new(Mutex::new(AppState::default()));
+    let stopping = Arc::new(AtomicBool::new(false));
+    let stopping_for_write = Arc::clone(&stopping);
     let state_for_write = Arc::clone(&state);
     let state_for_read  = Arc::clone(&state);
     let app = Application {
@@ -40,40 +48,44 @@ async fn main() {
                         write_without_response: true,
                         method: CharacteristicWriteMethod::Fun(Box::new(move |data, _req| {
                             let state_for_write = Arc::clone(&state_for_write);
+                            let stopping = Arc::clone(&stopping_for_write);
+                            let seq = FRAME_SEQ.fetch_add(1, Ordering::Relaxed) + 1;
+                            let span = info_span!("frame", seq, len = data.len(), format = field::Empty, grid = field::Empty, states = field::Empty);
                             async move {
-                                print!("RX {} bytes: [", data.len());
-                                for (i, b) in data.iter().enumerate() {
-                                    if i > 0 { print!(" "); }
-                                    print!("{:02X}", b);
-                                }
-                                println!("]");
+                                if stopping.load(Ordering::SeqCst) { return Err(ReqError::Failed); }
+                                let span = Span::current();
+                                debug!("RX {} bytes: [{}]", data.len(), data.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" "));
                                 {
                                     let mut st = state_for_write.lock().await;
                                     st.last_raw = data.clone();
                                 }
                                 if !data.is_empty() && (data[0] == b'{' || data[0] == b'[') {
+                                    span.record("format", "json");
                                     if let Some(grid) = parse_json_grid(&data) {
                                         let mut st = state_for_write.lock().await;
                                         let gf = GridFrame { rows: grid.len(), cols: grid.get(0).map(|r| r.len()).unwrap_or(0), data: grid.clone() };
+                                        span.record("grid", format!("{}x{}", gf.rows, gf.cols));
                                         st.last_grid = Some(gf.clone());
                                         st.history.push_back(gf);
                                         while st.history.len() > HISTORY_MAX { st.history.pop_front(); }
                                         let states = grid_to_node_states_4(&grid);
-                                        send_bytes_to_feather(&states);
+                                        span.record("states", field::debug(&states));
+                                        if forward_to_feather(&states, &stopping) { info!("Frame forwarded"); }
                                     } else {
-                                        println!("JSON detected but failed to parse as 2D floats.");
+                                        warn!("JSON detected but failed to parse as 2D floats.");
                                     }
                                 } else {
+                                    span.record("format", "raw");
                                     if data.len() >= 6 {
                                         let to_send = &data[..6];
-                                        println!("Forwarding raw 6-byte states: {:?}", to_send);
-                                        send_bytes_to_feather(to_send);
+                                        span.record("states", field::debug(to_send));
+                                        if forward_to_feather(to_send, &stopping) { info!("Forwarded raw 6-byte states"); }
                                     } else {
-                                        println!("Not JSON and < 6 bytes; ignoring.");
+                                        warn!("Not JSON and < 6 bytes; ignoring.");
                                     }
                                 }
                                 Ok(())
-                            }.boxed()
+                            }.instrument(span).boxed()
                         })),
                         ..Default::default()
                     }),
@@ -102,19 +114,38 @@ async fn main() {
         }],
         ..Default::default()
     };
-    let _app_handle = adapter.serve_gatt_application(app).await.expect("serve gatt");
-    println!("BLE receiver is up. Using serial: {SERIAL_PATH}");
-    loop { sleep(Duration::from_secs(60)).await; }
+    let app_handle = adapter.serve_gatt_application(app).await.expect("serve gatt");
+    info!("BLE receiver is up. Using serial: {SERIAL_PATH}");
+    let sig = shutdown::wait_for_signal().await.expect("install signal handlers");
+    info!("Received {sig:?}; driving safe state {SAFE_STATE:?} and shutting down");
+    let safe = {
+        let _serial = SERIAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        stopping.store(true, Ordering::SeqCst);
+        send_bytes_to_feather(&SAFE_STATE)
+    };
+    drop(adv_handle);
+    drop(app_handle);
+    // bluer unregisters both from tasks spawned on drop; let them run before the runtime goes.
+    tokio::time::sleep(UNREGISTER_GRACE).await;
+    ExitCode::from(if safe { EXIT_OK } else { EXIT_SAFE_STATE_FAILED })
+}
+
+/// Sends a frame unless shutdown has begun, checked under the serial lock so nothing lands after the safe state.
+fn forward_to_feather(bytes: &[u8], stopping: &AtomicBool) -> bool {
+    let _serial = SERIAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if stopping.load(Ordering::SeqCst) { warn!("Shutting down; dropping frame"); return false; }
+    send_bytes_to_feather(bytes)
 }
 
-fn send_bytes_to_feather(bytes: &[u8]) {
+fn send_bytes_to_feather(bytes: &[u8]) -> bool {
     match serialport::new(SERIAL_PATH, 115_200).timeout(Duration::from_millis(100)).open() {
         Ok(mut port) => {
-            if let Err(e) = port.write_all(bytes) { eprintln!("UART write failed: {e:?}"); return; }
-            let _ = port.flush();
+            if let Err(e) = port.write_all(bytes) { error!("UART write failed: {e:?}"); return false; }
+            let flushed = port.flush().is_ok();
             std::thread::sleep(Duration::from_millis(5));
+            flushed
         }
-        Err(e) => eprintln!("Could not open {SERIAL_PATH}: {e:?}"),
+        Err(e) => { error!("Could not open {SERIAL_PATH}: {e:?}"); false }
     }
 }
 
@@ -1,73 +1,194 @@
+use ble_receiver::{
+    advertising::{self, STATUS_OWNED, STATUS_SERIAL_OK},
+    approach::ApproachTracker,
+    arbitration::WriteVerdict,
+    bearing::SectorMap,
+    config::Config,
+    connection::{watch_links, LinkEvent},
+    control::parse_command,
+    danger::{StopDetector, StopStatus},
+    dropoff::DropOffAlert,
+    logging,
+    mapping::GridMapper,
+    metrics::{self, Metrics},
+    pattern::{PatternEngine, PatternLibrary},
+    profile::ProfileStore,
+    shutdown::{self, DRAIN_TIMEOUT, EXIT_OK, EXIT_SAFE_STATE_FAILED, EXIT_USAGE, UNREGISTER_GRACE},
+    standard_services,
+    state::AppState,
+    systemd::{self, Journal, Notifier},
+    worker::{handle_command, handle_write, spawn_worker, Sink, Worker, WorkerMsg},
+};
 use bluer::{
-    adv::Advertisement,
+    adv::AdvertisementHandle,
     gatt::local::{
-        Application, Characteristic, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod,
-        Service,
+        Application, Characteristic, CharacteristicNotifier, CharacteristicNotify, CharacteristicNotifyMethod,
+        CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, ReqError, Service,
     },
-    Uuid,
+    Adapter, Uuid,
 };
 use futures::FutureExt;
-use log::{error, info, warn};
 use std::{
-    collections::{BTreeSet, VecDeque},
-    io::Write as _,
+    process::ExitCode,
     sync::{Arc, Mutex as StdMutex},
-    time::Duration,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, oneshot, watch, Mutex},
+    task::JoinHandle,
+    time::{sleep, timeout},
 };
-use tokio::{sync::{mpsc, Mutex}, time::sleep};
+use tracing::{error, info, warn};
 
 const SRV_UUID: Uuid = Uuid::from_u128(0x8b322909_2d3b_447b_a4d5_dfe0c009ec5a);
 const WR_CHAR_UUID: Uuid = Uuid::from_u128(0x8b32290a_2d3b_447b_a4d5_dfe0c009ec5a);
+const CTRL_UUID: Uuid = Uuid::from_u128(0x8b32290b_2d3b_447b_a4d5_dfe0c009ec5a);
 const INFO_UUID: Uuid = Uuid::from_u128(0x8b32290c_2d3b_447b_a4d5_dfe0c009ec5a);
+const STOP_UUID: Uuid = Uuid::from_u128(0x8b32290d_2d3b_447b_a4d5_dfe0c009ec5a);
+const PROFILE_UUID: Uuid = Uuid::from_u128(0x8b32290e_2d3b_447b_a4d5_dfe0c009ec5a);
 
-const SERIAL_PATH: &str = "/dev/serial/by-id/usb-Adafruit_Feather_RP2040_DF648C86534125530-if00";
-const HISTORY_MAX: usize = 8;
-
-#[derive(Clone, Debug)]
-struct GridFrame {
-    rows: usize,
-    cols: usize,
-    data: Vec<Vec<f32>>,
-}
-
-#[derive(Default)]
-struct AppState {
-    last_raw: Vec<u8>,
-    last_grid: Option<GridFrame>,
-    history: VecDeque<GridFrame>,
-}
+const ADV_REFRESH: Duration = Duration::from_secs(2);
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
-    env_logger::init();
+async fn main() -> ExitCode {
+    let config = Config::from_args().expect("load config");
+    let library = match &config.patterns {
+        Some(path) => PatternLibrary::load(path).expect("load patterns"),
+        None => PatternLibrary::default(),
+    };
+    let mut profiles = match &config.profiles {
+        Some(path) => ProfileStore::load(path).expect("load profiles"),
+        None => ProfileStore::default(),
+    };
+    // `--list-profiles` prints the profiles and exits; `--profile <name>` switches before starting.
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--list-profiles" {
+            println!("{}", profiles.to_json());
+            return ExitCode::SUCCESS;
+        }
+        if arg == "--profile" {
+            let name = args.next().unwrap_or_default();
+            if profiles.select(&name).is_none() {
+                let known: Vec<&str> = profiles.names().collect();
+                eprintln!("No profile named {name:?}; known profiles: {}", known.join(", "));
+                return ExitCode::from(EXIT_USAGE);
+            }
+            profiles.save().expect("save profiles");
+        }
+    }
+    let profile = profiles.active().copied();
+    let tui_mode = std::env::args().any(|a| a == "--tui");
+    logging::init(logging::json_path(config.log_json.as_deref()).as_deref(), !tui_mode).expect("open JSON log");
 
-    let serial_port = open_serial_once(SERIAL_PATH, 115_200);
-    let serial_port = Arc::new(StdMutex::new(serial_port));
+    let serial_port = open_serial_once(&config.serial_path, 115_200);
+    let serial_port: Sink = Arc::new(StdMutex::new(Box::new(serial_port)));
 
-    let state = Arc::new(Mutex::new(AppState::default()));
+    let state = Arc::new(Mutex::new(AppState {
+        serial_ok: true,
+        profiles,
+        ..Default::default()
+    }));
+
+    let (tx, rx) = mpsc::unbounded_channel::<WorkerMsg>();
+    let journal = if config.journal_fields { Journal::from_env() } else { None };
+    let metrics = Arc::new(Metrics::default());
+    let (stops_tx, stops_rx) = watch::channel(StopStatus::default());
+    if let Some(addr) = config.metrics_addr.clone() {
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(&addr, metrics).await {
+                error!("Metrics endpoint on {addr} stopped: {e}");
+            }
+        });
+    }
 
-    let (tx, rx) = mpsc::unbounded_channel::<Vec<u8>>();
-    spawn_worker(rx, Arc::clone(&state), Arc::clone(&serial_port));
+    let (mut grid, mut depth) = (config.grid, config.depth);
+    if let Some(profile) = &profile {
+        profile.tune(&mut grid, &mut depth);
+    }
+    spawn_worker(
+        rx,
+        Worker {
+            state: Arc::clone(&state),
+            sink: Arc::clone(&serial_port),
+            safe_state: config.safe_state,
+            raw_policy: config.raw,
+            mapper: GridMapper::new(grid),
+            depth,
+            sectors: SectorMap::new(config.bearings),
+            approach: ApproachTracker::new(config.approach),
+            stop: StopDetector::new(config.danger),
+            drop_off: DropOffAlert::new(config.drop_off),
+            patterns: PatternEngine::new(config.safe_state),
+            library,
+            profile: profile.unwrap_or_default(),
+            calibration: config.calibration,
+            stops: stops_tx,
+            journal,
+            metrics: Arc::clone(&metrics),
+        },
+    );
+
+    // `--play <name>` plays a pattern from the pattern file at startup, e.g. to try one out.
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--play" {
+            if let Some(name) = args.next() {
+                let _ = tx.send(WorkerMsg::Play(name));
+            }
+        }
+    }
+
+    spawn_depth_ws(&config, Arc::clone(&state), tx.clone(), stops_rx.clone(), Arc::clone(&metrics));
 
     let session = bluer::Session::new().await.expect("create bluer session");
-    let adapter = session.default_adapter().await.expect("get default adapter");
+    let adapter = advertising::select_adapter(&session, config.adapter.as_deref())
+        .await
+        .expect("get adapter");
     adapter.set_powered(true).await.expect("power on adapter");
 
-    let mut svc = BTreeSet::new();
-    svc.insert(SRV_UUID);
-
-    let adv = Advertisement {
-        service_uuids: svc,
-        discoverable: Some(true),
-        local_name: Some("WHV Haptic Receiver".to_string()),
-        ..Default::default()
-    };
-    let _adv_handle = adapter.advertise(adv).await.expect("start advertising");
+    let status = adv_status(&*state.lock().await);
+    let adv_handle = adapter
+        .advertise(advertising::build(&config, SRV_UUID, status))
+        .await
+        .expect("start advertising");
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let advertiser = spawn_advertiser(
+        adapter.clone(),
+        config.clone(),
+        Arc::clone(&state),
+        status,
+        adv_handle,
+        shutdown_rx,
+    );
+
+    let (link_tx, link_rx) = mpsc::unbounded_channel::<LinkEvent>();
+    spawn_link_tracker(link_rx, Arc::clone(&state), tx.clone(), Arc::clone(&metrics));
+    {
+        let adapter = adapter.clone();
+        let link_tx = link_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = watch_links(adapter, link_tx).await {
+                error!("Connection tracking stopped: {e}");
+            }
+        });
+    }
 
     let tx_for_write = tx.clone();
+    let link_tx_for_write = link_tx.clone();
+    let link_tx_for_read = link_tx.clone();
+    let state_for_write = Arc::clone(&state);
+    let state_for_ctrl = Arc::clone(&state);
+    let tx_for_ctrl = tx.clone();
     let state_for_read = Arc::clone(&state);
+    let stops_for_read = stops_rx.clone();
+    let stops_for_notify = stops_rx.clone();
+    let state_for_profiles = Arc::clone(&state);
+    let state_for_switch = Arc::clone(&state);
+    let tx_for_profile = tx.clone();
 
-    let app = Application {
+    let mut app = Application {
         services: vec![Service {
             uuid: SRV_UUID,
             primary: true,
@@ -77,11 +198,30 @@ async fn main() {
                     write: Some(CharacteristicWrite {
                         write: true,
                         write_without_response: true,
-                        method: CharacteristicWriteMethod::Fun(Box::new(move |data, _req| {
+                        method: CharacteristicWriteMethod::Fun(Box::new(move |data, req| {
                             let tx_for_write = tx_for_write.clone();
+                            let state_for_write = Arc::clone(&state_for_write);
+                            let _ = link_tx_for_write.send(LinkEvent::Mtu(req.device_address, req.mtu));
+                            async move { handle_write(&state_for_write, &tx_for_write, req.device_address, data).await }
+                            .boxed()
+                        })),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                Characteristic {
+                    uuid: CTRL_UUID,
+                    write: Some(CharacteristicWrite {
+                        write: true,
+                        method: CharacteristicWriteMethod::Fun(Box::new(move |data, req| {
+                            let state_for_ctrl = Arc::clone(&state_for_ctrl);
+                            let tx_for_ctrl = tx_for_ctrl.clone();
                             async move {
-                                let _ = tx_for_write.send(data.clone());
-                                Ok(())
+                                let Some(cmd) = parse_command(&data) else {
+                                    warn!("Unrecognised control command from {}", req.device_address);
+                                    return Err(ReqError::NotSupported);
+                                };
+                                handle_command(&state_for_ctrl, &tx_for_ctrl, req.device_address, cmd).await
                             }
                             .boxed()
                         })),
@@ -93,10 +233,12 @@ async fn main() {
                     uuid: INFO_UUID,
                     read: Some(CharacteristicRead {
                         read: true,
-                        fun: Box::new(move |_req| {
+                        fun: Box::new(move |req| {
                             let state_for_read = Arc::clone(&state_for_read);
+                            let _ = link_tx_for_read.send(LinkEvent::Mtu(req.device_address, req.mtu));
                             async move {
-                                let st = state_for_read.lock().await;
+                                let mut st = state_for_read.lock().await;
+                                st.arbiter.seen(req.device_address, Instant::now());
                                 let raw_len = st.last_raw.len();
                                 let (rows, cols) = st
                                     .last_grid
@@ -105,11 +247,14 @@ async fn main() {
                                     .unwrap_or((0, 0));
 
                                 let s = format!(
-                                    "WHV Pi5 Receiver | last_raw={} bytes | last_grid={}x{} | history={}",
+                                    "WHV Pi5 Receiver | last_raw={} bytes | last_grid={}x{} | history={} | profile={} | {} {}",
                                     raw_len,
                                     rows,
                                     cols,
-                                    st.history.len()
+                                    st.history.len(),
+                                    st.profiles.active_name().unwrap_or("none"),
+                                    st.arbiter.status(),
+                                    st.links.status()
                                 );
                                 Ok(s.into_bytes())
                             }
@@ -119,156 +264,346 @@ async fn main() {
                     }),
                     ..Default::default()
                 },
+                Characteristic {
+                    uuid: STOP_UUID,
+                    read: Some(CharacteristicRead {
+                        read: true,
+                        fun: Box::new(move |_req| {
+                            let reply = stops_for_read.borrow().to_json();
+                            async move { Ok(reply.into_bytes()) }.boxed()
+                        }),
+                        ..Default::default()
+                    }),
+                    notify: Some(CharacteristicNotify {
+                        notify: true,
+                        method: CharacteristicNotifyMethod::Fun(Box::new(move |notifier| {
+                            notify_stops(notifier, stops_for_notify.clone()).boxed()
+                        })),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                Characteristic {
+                    uuid: PROFILE_UUID,
+                    read: Some(CharacteristicRead {
+                        read: true,
+                        fun: Box::new(move |_req| {
+                            let state_for_profiles = Arc::clone(&state_for_profiles);
+                            async move { Ok(state_for_profiles.lock().await.profiles.to_json().into_bytes()) }.boxed()
+                        }),
+                        ..Default::default()
+                    }),
+                    write: Some(CharacteristicWrite {
+                        write: true,
+                        method: CharacteristicWriteMethod::Fun(Box::new(move |data, req| {
+                            let state_for_switch = Arc::clone(&state_for_switch);
+                            let tx_for_profile = tx_for_profile.clone();
+                            async move {
+                                let name = String::from_utf8_lossy(&data).trim().to_string();
+                                let mut st = state_for_switch.lock().await;
+                                if let WriteVerdict::Rejected { owner } =
+                                    st.arbiter.check_command(req.device_address, Instant::now())
+                                {
+                                    let from = req.device_address;
+                                    warn!("Ignoring profile {name:?} from {from}; control is owned by {owner}");
+                                    return Err(ReqError::NotAuthorized);
+                                }
+                                let Some(profile) = st.profiles.select(&name) else {
+                                    warn!("{} asked for unknown profile {name:?}", req.device_address);
+                                    return Err(ReqError::NotSupported);
+                                };
+                                info!("{} switched to profile {name:?}", req.device_address);
+                                // Saved off the runtime thread; the lock is not held for the write.
+                                let store = st.profiles.clone();
+                                drop(st);
+                                tokio::task::spawn_blocking(move || {
+                                    if let Err(e) = store.save() {
+                                        warn!("Could not save the active profile: {e}");
+                                    }
+                                });
+                                let _ = tx_for_profile.send(WorkerMsg::Profile(profile));
+                                Ok(())
+                            }
+                            .boxed()
+                        })),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
             ],
             ..Default::default()
         }],
         ..Default::default()
     };
+    app.services
+        .push(standard_services::device_information_service(&config.device, config.unit_id));
+    if let Some(source) = config.battery.clone() {
+        app.services.push(standard_services::battery_service(source));
+    }
 
-    let _app_handle = adapter
+    let app_handle = adapter
         .serve_gatt_application(app)
         .await
         .expect("serve gatt");
 
-    info!("BLE receiver is up. Service={SRV_UUID} WriteChar={WR_CHAR_UUID} Serial={SERIAL_PATH}");
+    info!(
+        "BLE receiver is up as {:?} on {}. Unit={}",
+        advertising::local_name(config.name_suffix.as_deref()),
+        adapter.name(),
+        config.unit_id
+    );
+    let notifier = if config.sd_notify {
+        Notifier::from_env().unwrap_or_else(|e| {
+            warn!("NOTIFY_SOCKET is set but unusable: {e}");
+            None
+        })
+    } else {
+        None
+    }
+    .map(Arc::new);
+    if let Some(n) = &notifier {
+        let _ = n.ready();
+        let _ = n.status("Serving GATT; serial open");
+        if let Some(interval) = systemd::watchdog_interval() {
+            spawn_watchdog(Arc::clone(n), interval, tx.clone(), Arc::clone(&metrics));
+        }
+    }
 
-    loop {
-        sleep(Duration::from_secs(60)).await;
+    info!(
+        "Service={SRV_UUID} WriteChar={WR_CHAR_UUID} CtrlChar={CTRL_UUID} StopChar={STOP_UUID} ProfileChar={PROFILE_UUID} Serial={}",
+        config.serial_path
+    );
+
+    let reason = tokio::select! {
+        sig = shutdown::wait_for_signal() => format!("{:?}", sig.expect("install signal handlers")),
+        res = run_tui(Arc::clone(&state), tui_mode) => format!("TUI closed ({res:?})"),
+    };
+    info!("{reason}; shutting down");
+
+    if let Some(n) = &notifier {
+        let _ = n.stopping();
     }
+    state.lock().await.shutting_down = true;
+
+    let (done_tx, done_rx) = oneshot::channel();
+    let drained = if tx.send(WorkerMsg::Shutdown(done_tx)).is_ok() {
+        match timeout(DRAIN_TIMEOUT, done_rx).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(_)) => Err(std::io::Error::other("worker exited before draining")),
+            Err(_) => Err(std::io::Error::other("timed out driving safe state")),
+        }
+    } else {
+        Err(std::io::Error::other("worker already stopped"))
+    };
+
+    let _ = shutdown_tx.send(true);
+    let _ = advertiser.await;
+    drop(app_handle);
+    // bluer unregisters from tasks spawned on drop; they need the runtime for a moment yet.
+    sleep(UNREGISTER_GRACE).await;
+    info!("Advertisement and GATT application removed");
+
+    let code = match drained {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            error!("Safe state not confirmed: {e}");
+            EXIT_SAFE_STATE_FAILED
+        }
+    };
+    ExitCode::from(code)
 }
 
-fn open_serial_once(path: &str, baud: u32) -> Box<dyn serialport::SerialPort> {
-    serialport::new(path, baud)
-        .timeout(Duration::from_millis(200))
-        .open()
-        .unwrap_or_else(|e| panic!("Could not open serial port {path}: {e:?}"))
+#[cfg(feature = "tui")]
+async fn run_tui(state: Arc<Mutex<AppState>>, enabled: bool) -> std::io::Result<()> {
+    if enabled {
+        ble_receiver::tui::run(state).await
+    } else {
+        std::future::pending().await
+    }
 }
 
-fn spawn_worker(
-    mut rx: mpsc::UnboundedReceiver<Vec<u8>>,
+#[cfg(not(feature = "tui"))]
+async fn run_tui(_state: Arc<Mutex<AppState>>, enabled: bool) -> std::io::Result<()> {
+    if enabled {
+        warn!("Built without the `tui` feature; ignoring --tui");
+    }
+    std::future::pending().await
+}
+
+#[cfg(feature = "ws")]
+fn spawn_depth_ws(
+    config: &Config,
     state: Arc<Mutex<AppState>>,
-    serial_port: Arc<StdMutex<Box<dyn serialport::SerialPort>>>,
+    tx: mpsc::UnboundedSender<WorkerMsg>,
+    stops: watch::Receiver<StopStatus>,
+    metrics: Arc<Metrics>,
 ) {
+    let Some(addr) = config.depth_ws_addr.clone() else {
+        return;
+    };
+    let processing = ble_receiver::depth_ws::Processing {
+        ground: config.ground,
+        drop_off: config.drop_off,
+        binning: config.binning,
+        zone: config.danger,
+    };
     tokio::spawn(async move {
-        while let Some(data) = rx.recv().await {
-            {
-                let mut st = state.lock().await;
-                st.last_raw = data.clone();
-            }
-
-            info!("RX {} bytes", data.len());
-
-            if !data.is_empty() && (data[0] == b'{' || data[0] == b'[') {
-                if let Some(grid) = parse_json_grid(&data) {
-                    let gf = GridFrame {
-                        rows: grid.len(),
-                        cols: grid.get(0).map(|r| r.len()).unwrap_or(0),
-                        data: grid.clone(),
-                    };
-
-                    {
-                        let mut st = state.lock().await;
-                        st.last_grid = Some(gf.clone());
-                        st.history.push_back(gf);
-                        while st.history.len() > HISTORY_MAX {
-                            st.history.pop_front();
-                        }
-                    }
-
-                    let states = grid_to_node_states_4(&grid);
-                    if let Err(e) = write_serial_bytes(&serial_port, &states) {
-                        error!("UART write failed: {e:?}");
-                    }
-                    continue;
-                } else {
-                    warn!("JSON detected but failed to parse as 2D floats");
-                    continue;
-                }
-            }
-
-            if data.len() >= 6 {
-                let to_send = &data[..6];
-                if let Err(e) = write_serial_bytes(&serial_port, to_send) {
-                    error!("UART write failed: {e:?}");
-                }
-            } else {
-                warn!("Not JSON and < 6 bytes; ignoring (len={})", data.len());
-            }
+        if let Err(e) = ble_receiver::depth_ws::serve(&addr, processing, state, tx, stops, metrics).await {
+            error!("Depth WebSocket on {addr} stopped: {e}");
         }
     });
 }
 
-fn write_serial_bytes(
-    port: &Arc<StdMutex<Box<dyn serialport::SerialPort>>>,
-    bytes: &[u8],
-) -> std::io::Result<()> {
-    let mut guard = port
-        .lock()
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "serial mutex poisoned"))?;
-    guard.write_all(bytes)?;
-    guard.flush()?;
-    Ok(())
+#[cfg(not(feature = "ws"))]
+fn spawn_depth_ws(
+    config: &Config,
+    _state: Arc<Mutex<AppState>>,
+    _tx: mpsc::UnboundedSender<WorkerMsg>,
+    _stops: watch::Receiver<StopStatus>,
+    _metrics: Arc<Metrics>,
+) {
+    if config.depth_ws_addr.is_some() {
+        warn!("Built without the `ws` feature; ignoring depth_ws_addr");
+    }
 }
 
-fn parse_json_grid(bytes: &[u8]) -> Option<Vec<Vec<f32>>> {
-    if let Ok(v) = serde_json::from_slice::<Vec<Vec<f32>>>(bytes) {
-        if is_rectangular(&v) {
-            return Some(v);
+/// Notifies a subscribed phone whenever a stop or drop-off is raised or cleared; per-frame
+/// updates stay on the WebSocket.
+async fn notify_stops(mut notifier: CharacteristicNotifier, mut stops: watch::Receiver<StopStatus>) {
+    let mut sent = None;
+    loop {
+        let status = *stops.borrow_and_update();
+        let alerts = (status.stop, status.drop_m.is_some());
+        if sent != Some(alerts) {
+            if let Err(e) = notifier.notify(status.to_json().into_bytes()).await {
+                info!("Stop notifications ended: {e}");
+                return;
+            }
+            sent = Some(alerts);
         }
-    }
-
-    #[derive(serde::Deserialize)]
-    struct Obj {
-        grid: Vec<Vec<f32>>,
-    }
-
-    if let Ok(obj) = serde_json::from_slice::<Obj>(bytes) {
-        if is_rectangular(&obj.grid) {
-            return Some(obj.grid);
+        tokio::select! {
+            changed = stops.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = notifier.stopped() => return,
         }
     }
+}
 
-    None
+fn open_serial_once(path: &str, baud: u32) -> Box<dyn serialport::SerialPort> {
+    serialport::new(path, baud)
+        .timeout(Duration::from_millis(200))
+        .open()
+        .unwrap_or_else(|e| panic!("Could not open serial port {path}: {e:?}"))
 }
 
-fn is_rectangular(v: &Vec<Vec<f32>>) -> bool {
-    if v.is_empty() {
-        return true;
+fn adv_status(st: &AppState) -> u8 {
+    let mut status = 0;
+    if st.serial_ok {
+        status |= STATUS_SERIAL_OK;
     }
-    let cols = v[0].len();
-    v.iter().all(|r| r.len() == cols)
+    if st.arbiter.owner().is_some() {
+        status |= STATUS_OWNED;
+    }
+    status
 }
 
-fn grid_to_node_states_4(grid: &Vec<Vec<f32>>) -> [u8; 6] {
-    let mut states = [4u8; 6];
+fn spawn_advertiser(
+    adapter: Adapter,
+    config: Config,
+    state: Arc<Mutex<AppState>>,
+    mut status: u8,
+    handle: AdvertisementHandle,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut handle = Some(handle);
+        loop {
+            tokio::select! {
+                _ = sleep(ADV_REFRESH) => {}
+                _ = shutdown.changed() => break,
+            }
 
-    if grid.is_empty() || grid[0].is_empty() {
-        return states;
-    }
+            let now = adv_status(&*state.lock().await);
+            if now == status && handle.is_some() {
+                continue;
+            }
 
-    let rows = grid.len().min(2);
-    let cols = grid[0].len().min(3);
+            drop(handle.take());
+            match adapter.advertise(advertising::build(&config, SRV_UUID, now)).await {
+                Ok(h) => {
+                    info!("Advertising status {status:#04x} -> {now:#04x}");
+                    handle = Some(h);
+                    status = now;
+                }
+                Err(e) => error!("Re-advertising failed: {e}"),
+            }
+        }
+        drop(handle);
+    })
+}
 
-    for r in 0..rows {
-        for c in 0..cols {
-            let idx = r * 3 + c;
-            let mut v = grid[r][c];
-            if v.is_nan() {
-                v = 0.0;
+fn spawn_link_tracker(
+    mut link_rx: mpsc::UnboundedReceiver<LinkEvent>,
+    state: Arc<Mutex<AppState>>,
+    tx: mpsc::UnboundedSender<WorkerMsg>,
+    metrics: Arc<Metrics>,
+) {
+    tokio::spawn(async move {
+        while let Some(evt) = link_rx.recv().await {
+            let mut st = state.lock().await;
+            let down = st.link_event(evt, Instant::now());
+
+            match (evt, down) {
+                (LinkEvent::Connected(addr), _) => {
+                    let connects = st.links.get(&addr).map(|l| l.connects).unwrap_or(0);
+                    info!("Central {addr} connected (#{connects})");
+                    if connects > 1 {
+                        Metrics::inc(&metrics.reconnects);
+                    }
+                }
+                (LinkEvent::Disconnected(addr), Some(down)) => {
+                    info!(
+                        "Central {addr} disconnected (owner={}, still connected={})",
+                        down.was_owner,
+                        st.links.connected_count()
+                    );
+                    if down.safe_state {
+                        let _ = tx.send(WorkerMsg::SafeState);
+                    }
+                }
+                _ => {}
             }
-            v = v.clamp(0.0, 1.0);
-
-            states[idx] = if v < 0.25 {
-                4
-            } else if v < 0.5 {
-                3
-            } else if v < 0.75 {
-                2
-            } else {
-                1
-            };
         }
-    }
+    });
+}
 
-    states
-}
\ No newline at end of file
+/// Pings the systemd watchdog only while the worker keeps answering, so a wedged worker gets
+/// the service restarted.
+fn spawn_watchdog(
+    notifier: Arc<Notifier>,
+    interval: Duration,
+    tx: mpsc::UnboundedSender<WorkerMsg>,
+    metrics: Arc<Metrics>,
+) {
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            let (pong_tx, pong_rx) = oneshot::channel();
+            if tx.send(WorkerMsg::Ping(pong_tx)).is_err() {
+                break;
+            }
+            match timeout(interval, pong_rx).await {
+                Ok(Ok(())) => {
+                    let _ = notifier.watchdog();
+                }
+                _ => {
+                    Metrics::inc(&metrics.watchdog_trips);
+                    warn!("Worker did not answer within {interval:?}; withholding watchdog ping");
+                }
+            }
+        }
+    });
+}
@@ -10,34 +10,14 @@ use esp_idf_svc::log::EspLogger;
 use log::{info, warn};
 use std::sync::{Arc, Mutex};
 
+// The payload decoders are shared with the receiver crate, which tests and fuzzes them.
+#[path = "mux.rs"]
+mod mux;
+use mux::{NodeCmd, MUX_NODES};
+
 const SERVICE_UUID: [u8; 16] = *b"\x5a\xec\x09\xc0\xe0\xdf\xd5\xa4\x7b\x44\x3b\x2d\x09\x29\x32\x8b";
 const WRITE_CHAR_UUID: [u8; 16] = *b"\x5a\xec\x09\xc0\xe0\xdf\xd5\xa4\x7b\x44\x3b\x2d\x0a\x29\x32\x8b";
 
-#[derive(Clone, Copy, Debug)]
-enum NodeCmd {
-    Hold,
-    Inflate,
-    Deflate,
-}
-
-impl NodeCmd {
-    fn from_3bit(v: u8) -> NodeCmd {
-        match v & 0b111 {
-            0b001 => NodeCmd::Inflate,
-            0b010 => NodeCmd::Deflate,
-            _ => NodeCmd::Hold,
-        }
-    }
-
-    fn to_state_bits(self) -> (bool, bool) {
-        match self {
-            NodeCmd::Hold => (false, false),
-            NodeCmd::Inflate => (true, false),
-            NodeCmd::Deflate => (false, true),
-        }
-    }
-}
-
 struct GpioMuxDriver {
     s0: PinDriver<'static, AnyOutputPin, Output>,
     s1: PinDriver<'static, AnyOutputPin, Output>,
@@ -83,8 +63,8 @@ impl GpioMuxDriver {
         Ok(())
     }
 
-    fn apply_frame(&mut self, cmds: [NodeCmd; 8]) -> Result<()> {
-        for i in 0u8..8 {
+    fn apply_frame(&mut self, cmds: [NodeCmd; MUX_NODES]) -> Result<()> {
+        for i in 0..MUX_NODES as u8 {
             self.select_node(i)?;
             self.set_cmd(cmds[i as usize])?;
             Ets::delay_us(10);
@@ -93,31 +73,6 @@ impl GpioMuxDriver {
     }
 }
 
-fn unpack_frame24(payload3: &[u8]) -> [NodeCmd; 8] {
-    let bits: u32 = (payload3[0] as u32) | ((payload3[1] as u32) << 8) | ((payload3[2] as u32) << 16);
-    let mut cmds = [NodeCmd::Hold; 8];
-    for i in 0..8 {
-        let v = ((bits >> (i * 3)) & 0x7) as u8;
-        cmds[i] = NodeCmd::from_3bit(v);
-    }
-    cmds
-}
-
-fn strengths8_to_cmds(payload8: &[u8]) -> [NodeCmd; 8] {
-    let mut cmds = [NodeCmd::Hold; 8];
-    for i in 0..8 {
-        let v = payload8[i];
-        cmds[i] = if v < 85 {
-            NodeCmd::Hold
-        } else if v < 170 {
-            NodeCmd::Inflate
-        } else {
-            NodeCmd::Deflate
-        };
-    }
-    cmds
-}
-
 fn main() -> Result<()> {
     EspLogger::initialize_default();
 
@@ -143,13 +98,7 @@ fn main() -> Result<()> {
         AttributeValue::new(vec![]),
         move |evt: WriteEvent| {
             let data = evt.data();
-            let cmds_opt = match data.len() {
-                3 => Some(unpack_frame24(data)),
-                8 => Some(strengths8_to_cmds(data)),
-                _ => None,
-            };
-
-            if let Some(cmds) = cmds_opt {
+            if let Some(cmds) = mux::decode(data) {
                 if let Ok(mut d) = driver_for_cb.lock() {
                     let _ = d.apply_frame(cmds);
                 }
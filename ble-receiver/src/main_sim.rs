@@ -0,0 +1,40 @@
+//! Virtual waistband: opens a pty that behaves like the Feather's USB serial port and prints
+//! one JSON line of simulated node pressure per tick.
+//!
+//!     whv-belt-sim [--link /tmp/whv-belt] [--interval-ms 200]
+//!
+//! Set `serial_path` in the receiver config to the printed path (or the `--link`).
+
+use ble_receiver::sim::{PtySim, SimParams};
+use std::{path::PathBuf, thread::sleep, time::Duration};
+
+fn main() {
+    let mut link: Option<PathBuf> = None;
+    let mut interval = Duration::from_millis(200);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--link" => link = args.next().map(PathBuf::from),
+            "--interval-ms" => {
+                let ms = args.next().and_then(|v| v.parse().ok()).expect("--interval-ms takes a number");
+                interval = Duration::from_millis(ms);
+            }
+            other => panic!("Unknown argument {other}"),
+        }
+    }
+
+    let sim = PtySim::spawn(SimParams::default()).expect("open pty");
+    eprintln!("Virtual waistband on {}", sim.path().display());
+
+    if let Some(link) = &link {
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(sim.path(), link).expect("create --link");
+        eprintln!("Linked as {}", link.display());
+    }
+
+    loop {
+        println!("{}", serde_json::to_string(&sim.snapshot()).expect("serialize snapshot"));
+        sleep(interval);
+    }
+}
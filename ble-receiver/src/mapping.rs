@@ -0,0 +1,253 @@
+use crate::{pattern::Pattern, NODE_COUNT};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt};
+
+/// Grid cells feeding the nodes, row-major: the top-left 2x3 of the grid.
+pub const NODE_ROWS: usize = 2;
+pub const NODE_COLS: usize = 3;
+
+/// What a node shows when its cell is unusable.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CellPolicy {
+    /// Pull into 0..=1; NaN counts as 0.0. The behaviour before policies existed.
+    Clamp,
+    /// Treat as 0.0, nothing nearby.
+    Far,
+    /// Treat as 1.0, obstacle right here.
+    Near,
+    /// Keep the node's last good value; unknown if there is none yet.
+    Hold,
+    /// Pulse the node so "no data" never feels like "far away".
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct GridPolicy {
+    /// NaN, infinities and `null` cells.
+    pub non_finite: CellPolicy,
+    /// Finite values outside 0..=1.
+    pub out_of_range: CellPolicy,
+    /// Nodes a short grid does not reach, e.g. the bottom row under a single-row grid, or with
+    /// bearings a sector nothing has seen lately. Far by default, as before policies existed, so
+    /// a short grid does not set half the belt pulsing.
+    pub outside_grid: CellPolicy,
+    /// Frames with more invalid cells than this (0..=1) are dropped whole; a cell is invalid when
+    /// its policy leaves it unknown.
+    pub max_invalid_fraction: f32,
+    /// The two states an unknown node alternates between.
+    pub unknown_pulse: [u8; 2],
+    pub unknown_blink_ms: u64,
+    /// Proximity at which a node steps up to states 3, 2 and 1.
+    pub thresholds: [f32; 3],
+    /// Frames each node's value is averaged over to steady jittery depth; 1 takes the latest alone.
+    pub history: usize,
+}
+
+impl Default for GridPolicy {
+    fn default() -> Self {
+        Self {
+            non_finite: CellPolicy::Unknown,
+            out_of_range: CellPolicy::Clamp,
+            outside_grid: CellPolicy::Far,
+            max_invalid_fraction: 0.5,
+            unknown_pulse: [2, 4],
+            unknown_blink_ms: 400,
+            thresholds: [0.25, 0.5, 0.75],
+            history: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridError {
+    Empty,
+    TooManyInvalid { invalid: usize, total: usize },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridError::Empty => write!(f, "grid has no cells"),
+            GridError::TooManyInvalid { invalid, total } => write!(f, "{invalid} of {total} cells invalid"),
+        }
+    }
+}
+
+/// Distances the phone used to scale itself (`MIN/MAX_DISTANCE` in the iOS app), now applied
+/// here to grids sent in meters.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct DepthRange {
+    /// At or closer than this is proximity 1.0.
+    pub near_m: f32,
+    /// At or beyond this is proximity 0.0.
+    pub far_m: f32,
+}
+
+impl Default for DepthRange {
+    fn default() -> Self {
+        Self { near_m: 0.3, far_m: 5.0 }
+    }
+}
+
+impl DepthRange {
+    /// Linear between the limits, like the phone; zero, negative and non-finite depths are no data.
+    pub fn proximity(&self, meters: f32) -> f32 {
+        if !meters.is_finite() || meters <= 0.0 {
+            return f32::NAN;
+        }
+        let span = (self.far_m - self.near_m).max(f32::EPSILON);
+        1.0 - (meters.clamp(self.near_m, self.far_m.max(self.near_m)) - self.near_m) / span
+    }
+
+    /// Inverse of `proximity`; NaN for values outside 0..=1.
+    pub fn meters(&self, proximity: f32) -> f32 {
+        if !(0.0..=1.0).contains(&proximity) {
+            return f32::NAN;
+        }
+        self.near_m + (1.0 - proximity) * (self.far_m - self.near_m).max(0.0)
+    }
+
+    pub fn to_proximity(&self, cells: &mut [Vec<f32>]) {
+        for v in cells.iter_mut().flatten() {
+            *v = self.proximity(*v);
+        }
+    }
+}
+
+/// Per-node proximity after the cell policy; `None` is unknown.
+pub type NodeValues = [Option<f32>; NODE_COUNT];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mapped {
+    pub values: NodeValues,
+    /// Cells anywhere in the grid, not just under the nodes, that their policy left unknown.
+    pub invalid: usize,
+}
+
+/// Turns grids into per-node proximity, remembering the last good value for `Hold` and the
+/// recent frames for averaging.
+#[derive(Clone, Debug)]
+pub struct GridMapper {
+    pub policy: GridPolicy,
+    held: NodeValues,
+    recent: VecDeque<NodeValues>,
+}
+
+impl GridMapper {
+    pub fn new(policy: GridPolicy) -> Self {
+        Self {
+            policy,
+            held: [None; NODE_COUNT],
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// States to send for freshly mapped `values`, unknown nodes on the first pulse state.
+    pub fn states(&self, values: &NodeValues) -> [u8; NODE_COUNT] {
+        node_states(values, self.policy.unknown_pulse[0], &self.policy.thresholds)
+    }
+
+    /// The pulse on the unknown nodes of `values`, if there are any.
+    pub fn unknown_pulse(&self, values: &NodeValues) -> Option<Pattern> {
+        let unknown: Vec<usize> = (0..NODE_COUNT).filter(|&n| values[n].is_none()).collect();
+        (!unknown.is_empty()).then(|| Pattern::pulse(&unknown, self.policy.unknown_pulse, self.policy.unknown_blink_ms))
+    }
+
+    pub fn map(&mut self, grid: &[Vec<f32>]) -> Result<Mapped, GridError> {
+        self.map_with(grid, |idx| {
+            grid.get(idx / NODE_COLS)
+                .and_then(|row| row.get(idx % NODE_COLS))
+                .copied()
+        })
+    }
+
+    /// Like `map`, with node `idx` reading `cell(idx)` instead of the grid's top-left cells;
+    /// `None` means the grid does not reach the node.
+    pub fn map_with(&mut self, grid: &[Vec<f32>], cell: impl Fn(usize) -> Option<f32>) -> Result<Mapped, GridError> {
+        let total = grid.iter().map(|r| r.len()).sum::<usize>();
+        if total == 0 {
+            return Err(GridError::Empty);
+        }
+
+        let invalid = grid.iter().flatten().filter(|v| self.leaves_unknown(**v)).count();
+        if invalid as f32 > self.policy.max_invalid_fraction * total as f32 {
+            return Err(GridError::TooManyInvalid { invalid, total });
+        }
+
+        let mut values = [None; NODE_COUNT];
+        for (idx, value) in values.iter_mut().enumerate() {
+            *value = match cell(idx) {
+                Some(cell) if (0.0..=1.0).contains(&cell) => {
+                    self.held[idx] = Some(cell);
+                    Some(cell)
+                }
+                Some(cell) => self.apply(self.policy_for(cell), idx, cell),
+                None => self.apply(self.policy.outside_grid, idx, f32::NAN),
+            };
+        }
+
+        Ok(Mapped {
+            values: self.average(values),
+            invalid,
+        })
+    }
+
+    /// Each node's mean over the last `history` frames that knew it; unknown stays unknown.
+    fn average(&mut self, values: NodeValues) -> NodeValues {
+        self.recent.push_back(values);
+        while self.recent.len() > self.policy.history.max(1) {
+            self.recent.pop_front();
+        }
+        std::array::from_fn(|idx| {
+            values[idx]?;
+            let known: Vec<f32> = self.recent.iter().filter_map(|v| v[idx]).collect();
+            Some(known.iter().sum::<f32>() / known.len() as f32)
+        })
+    }
+
+    fn policy_for(&self, cell: f32) -> CellPolicy {
+        if cell.is_finite() {
+            self.policy.out_of_range
+        } else {
+            self.policy.non_finite
+        }
+    }
+
+    /// Whether a grid cell ends up unknown. Grid cells do not belong to a node, so under `Hold`
+    /// they count until the mapper has held something.
+    fn leaves_unknown(&self, cell: f32) -> bool {
+        if (0.0..=1.0).contains(&cell) {
+            return false;
+        }
+        match self.policy_for(cell) {
+            CellPolicy::Unknown => true,
+            CellPolicy::Hold => self.held.iter().all(Option::is_none),
+            _ => false,
+        }
+    }
+
+    fn apply(&self, policy: CellPolicy, idx: usize, cell: f32) -> Option<f32> {
+        match policy {
+            CellPolicy::Clamp if cell.is_nan() => Some(0.0),
+            CellPolicy::Clamp => Some(cell.clamp(0.0, 1.0)),
+            CellPolicy::Far => Some(0.0),
+            CellPolicy::Near => Some(1.0),
+            CellPolicy::Hold => self.held[idx],
+            CellPolicy::Unknown => None,
+        }
+    }
+}
+
+/// Four levels: under the first threshold (far) is state 4, the last and up (near) is state 1.
+pub fn proximity_to_state(v: f32, thresholds: &[f32; 3]) -> u8 {
+    4 - thresholds.iter().filter(|t| v >= **t).count() as u8
+}
+
+/// Node states for `values`, with unknown nodes on `unknown`.
+pub fn node_states(values: &NodeValues, unknown: u8, thresholds: &[f32; 3]) -> [u8; NODE_COUNT] {
+    let unknown = unknown.clamp(1, 4);
+    values.map(|v| v.map(|v| proximity_to_state(v, thresholds)).unwrap_or(unknown))
+}
@@ -0,0 +1,246 @@
+use std::{
+    fmt::Write as _,
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{lookup_host, TcpListener},
+};
+use tracing::{info, warn};
+
+/// Pause after a failed accept so a persistent error does not spin the runtime.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    Json,
+    Binary,
+    /// Binned from a full depth frame received over WebSocket.
+    Depth,
+    Raw,
+}
+
+impl FrameFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FrameFormat::Json => "json",
+            FrameFormat::Binary => "binary",
+            FrameFormat::Depth => "depth",
+            FrameFormat::Raw => "raw",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseFailure {
+    Json,
+    Binary,
+    Depth,
+    /// Raw frame not exactly one byte per node.
+    Length,
+    /// Raw frame rejected by the out-of-range policy.
+    OutOfRange,
+    /// Grid with no cells or too many invalid ones.
+    Grid,
+}
+
+/// Cumulative histogram with fixed buckets, in seconds.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, d: Duration) {
+        let secs = d.as_secs_f64();
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            if secs <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(d.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {}", bucket.load(Ordering::Relaxed));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub frames_json: AtomicU64,
+    pub frames_binary: AtomicU64,
+    pub frames_depth: AtomicU64,
+    pub frames_raw: AtomicU64,
+    pub parse_failures_json: AtomicU64,
+    pub parse_failures_binary: AtomicU64,
+    pub parse_failures_depth: AtomicU64,
+    pub parse_failures_length: AtomicU64,
+    pub parse_failures_out_of_range: AtomicU64,
+    pub parse_failures_grid: AtomicU64,
+    pub raw_state_violations: AtomicU64,
+    pub invalid_cells: AtomicU64,
+    pub stop_events: AtomicU64,
+    pub drop_off_events: AtomicU64,
+    pub serial_write_errors: AtomicU64,
+    pub reconnects: AtomicU64,
+    pub watchdog_trips: AtomicU64,
+    pub ble_to_serial_latency: Histogram,
+}
+
+impl Metrics {
+    pub fn frame(&self, format: FrameFormat) {
+        match format {
+            FrameFormat::Json => &self.frames_json,
+            FrameFormat::Binary => &self.frames_binary,
+            FrameFormat::Depth => &self.frames_depth,
+            FrameFormat::Raw => &self.frames_raw,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn parse_failure(&self, kind: ParseFailure) {
+        match kind {
+            ParseFailure::Json => &self.parse_failures_json,
+            ParseFailure::Binary => &self.parse_failures_binary,
+            ParseFailure::Depth => &self.parse_failures_depth,
+            ParseFailure::Length => &self.parse_failures_length,
+            ParseFailure::OutOfRange => &self.parse_failures_out_of_range,
+            ParseFailure::Grid => &self.parse_failures_grid,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Prometheus text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+
+        out.push_str("# HELP whv_frames_total Frames received on the write characteristic, by format.\n");
+        out.push_str("# TYPE whv_frames_total counter\n");
+        let _ = writeln!(out, "whv_frames_total{{format=\"json\"}} {}", get(&self.frames_json));
+        let _ = writeln!(out, "whv_frames_total{{format=\"binary\"}} {}", get(&self.frames_binary));
+        let _ = writeln!(out, "whv_frames_total{{format=\"depth\"}} {}", get(&self.frames_depth));
+        let _ = writeln!(out, "whv_frames_total{{format=\"raw\"}} {}", get(&self.frames_raw));
+
+        out.push_str("# HELP whv_parse_failures_total Frames dropped because they could not be decoded.\n");
+        out.push_str("# TYPE whv_parse_failures_total counter\n");
+        let _ = writeln!(out, "whv_parse_failures_total{{kind=\"json\"}} {}", get(&self.parse_failures_json));
+        let _ = writeln!(out, "whv_parse_failures_total{{kind=\"binary\"}} {}", get(&self.parse_failures_binary));
+        let _ = writeln!(out, "whv_parse_failures_total{{kind=\"depth\"}} {}", get(&self.parse_failures_depth));
+        let _ = writeln!(out, "whv_parse_failures_total{{kind=\"length\"}} {}", get(&self.parse_failures_length));
+        let _ = writeln!(
+            out,
+            "whv_parse_failures_total{{kind=\"out_of_range\"}} {}",
+            get(&self.parse_failures_out_of_range)
+        );
+        let _ = writeln!(out, "whv_parse_failures_total{{kind=\"grid\"}} {}", get(&self.parse_failures_grid));
+
+        for (name, help, c) in [
+            (
+                "whv_raw_state_violations_total",
+                "Raw node-state values outside the configured levels, rejected or clamped.",
+                &self.raw_state_violations,
+            ),
+            (
+                "whv_invalid_cells_total",
+                "Grid cells outside 0..1, NaN or null that the cell policy left unknown.",
+                &self.invalid_cells,
+            ),
+            ("whv_stop_events_total", "Obstacles in the danger zone that raised a stop.", &self.stop_events),
+            ("whv_drop_off_events_total", "Kerbs, stairs down and holes that raised a drop-off alert.", &self.drop_off_events),
+            ("whv_serial_write_errors_total", "Failed writes to the Feather serial port.", &self.serial_write_errors),
+            ("whv_reconnects_total", "Connections from centrals that had connected before.", &self.reconnects),
+            ("whv_watchdog_trips_total", "Watchdog pings withheld because the worker did not answer.", &self.watchdog_trips),
+        ] {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", get(c));
+        }
+
+        self.ble_to_serial_latency.render(
+            &mut out,
+            "whv_ble_to_serial_latency_seconds",
+            "Time from a BLE write arriving to its node states reaching the serial port.",
+        );
+        out
+    }
+}
+
+/// Binds the metrics listener. The endpoint has no authentication, so only loopback addresses
+/// are accepted; scrape it from elsewhere through an SSH tunnel or a local agent.
+pub async fn bind(addr: &str) -> io::Result<TcpListener> {
+    let addrs: Vec<_> = lookup_host(addr).await?.collect();
+    if addrs.is_empty() || addrs.iter().any(|a| !a.ip().is_loopback()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("metrics_addr {addr} is not a loopback address"),
+        ));
+    }
+    TcpListener::bind(&addrs[..]).await
+}
+
+/// Serves the current metrics over HTTP on `addr` at `/metrics` and `/`; other paths get a 404.
+/// Failed accepts, e.g. out of file descriptors, are logged and retried after `ACCEPT_BACKOFF`.
+pub async fn serve(addr: &str, metrics: Arc<Metrics>) -> io::Result<()> {
+    serve_on(bind(addr).await?, metrics).await
+}
+
+pub async fn serve_on(listener: TcpListener, metrics: Arc<Metrics>) -> io::Result<()> {
+    info!("Metrics on http://{}/metrics", listener.local_addr()?);
+
+    loop {
+        let mut sock = match listener.accept().await {
+            Ok((sock, _)) => sock,
+            Err(e) => {
+                warn!("Metrics accept failed: {e}");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let mut req = [0u8; 1024];
+            let n = match sock.read(&mut req).await {
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("Metrics request failed: {e}");
+                    return;
+                }
+            };
+            let line = String::from_utf8_lossy(&req[..n]);
+            let path = line.split_whitespace().nth(1).unwrap_or("/");
+
+            let resp = if path == "/" || path == "/metrics" {
+                let body = metrics.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+            let _ = sock.write_all(resp.as_bytes()).await;
+        });
+    }
+}
@@ -0,0 +1,63 @@
+//! Payload decoders for the 8-node GPIO-mux belt driven by the ESP32. `main_shell.rs` includes
+//! this file with `#[path]`, so it must stay free of crate-internal imports.
+
+/// Nodes behind the 3-bit mux.
+pub const MUX_NODES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeCmd {
+    Hold,
+    Inflate,
+    Deflate,
+}
+
+impl NodeCmd {
+    pub fn from_3bit(v: u8) -> NodeCmd {
+        match v & 0b111 {
+            0b001 => NodeCmd::Inflate,
+            0b010 => NodeCmd::Deflate,
+            _ => NodeCmd::Hold,
+        }
+    }
+
+    /// `(state_a, state_b)` pins for the selected node.
+    pub fn to_state_bits(self) -> (bool, bool) {
+        match self {
+            NodeCmd::Hold => (false, false),
+            NodeCmd::Inflate => (true, false),
+            NodeCmd::Deflate => (false, true),
+        }
+    }
+}
+
+/// Eight 3-bit commands packed little-endian into exactly 3 bytes.
+pub fn unpack_frame24(payload3: &[u8]) -> Option<[NodeCmd; MUX_NODES]> {
+    let &[b0, b1, b2] = payload3 else {
+        return None;
+    };
+    let bits = u32::from_le_bytes([b0, b1, b2, 0]);
+    Some(std::array::from_fn(|i| NodeCmd::from_3bit((bits >> (i * 3)) as u8)))
+}
+
+/// Exactly 8 strength bytes; thirds of the range map to hold, inflate and deflate.
+pub fn strengths8_to_cmds(payload8: &[u8]) -> Option<[NodeCmd; MUX_NODES]> {
+    let payload8: &[u8; MUX_NODES] = payload8.try_into().ok()?;
+    Some(payload8.map(|v| {
+        if v < 85 {
+            NodeCmd::Hold
+        } else if v < 170 {
+            NodeCmd::Inflate
+        } else {
+            NodeCmd::Deflate
+        }
+    }))
+}
+
+/// Picks the decoder by length, as the ESP32 write handler does.
+pub fn decode(payload: &[u8]) -> Option<[NodeCmd; MUX_NODES]> {
+    match payload.len() {
+        3 => unpack_frame24(payload),
+        MUX_NODES => strengths8_to_cmds(payload),
+        _ => None,
+    }
+}
@@ -0,0 +1,382 @@
+//! Time-based effects laid over the live node states on their way to the belt: pulses for
+//! urgency, sweeps for turns, throbs and double taps. Each effect plays in a slot whose
+//! priority decides which one a node shows when several want it.
+//!
+//! Named patterns can also be loaded from a JSON file so alerts can be tuned without a rebuild:
+//!
+//! ```json
+//! {
+//!   "turn_left": {
+//!     "priority": 20,
+//!     "repeat": 2,
+//!     "steps": [
+//!       { "states": [0, 0, 1, 0, 0, 1], "ms": 150 },
+//!       { "states": [0, 1, 0, 0, 1, 0], "ms": 150 },
+//!       { "states": [1, 0, 0, 1, 0, 0], "ms": 150 }
+//!     ]
+//!   }
+//! }
+//! ```
+
+use crate::NODE_COUNT;
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Steps shorter than this would outrun the pumps; they are stretched to it, and refused in files.
+pub const MIN_STEP: Duration = Duration::from_millis(20);
+
+/// Longest step and most repeats a pattern file may ask for.
+pub const MAX_STEP: Duration = Duration::from_secs(60);
+pub const MAX_REPEAT: u32 = 1000;
+
+/// Written for a node in a file step to let the live frame through, like `null`.
+pub const LIVE: u8 = 0;
+
+/// What each node shows for `ms`: a state, or `None` to let the live frame through.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Step {
+    #[serde(deserialize_with = "step_states")]
+    pub states: [Option<u8>; NODE_COUNT],
+    pub ms: u64,
+}
+
+fn step_states<'de, D: Deserializer<'de>>(d: D) -> Result<[Option<u8>; NODE_COUNT], D::Error> {
+    let states = <[Option<u8>; NODE_COUNT]>::deserialize(d)?;
+    Ok(states.map(|s| s.filter(|s| *s != LIVE)))
+}
+
+impl Step {
+    fn duration(&self) -> Duration {
+        Duration::from_millis(self.ms).max(MIN_STEP)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Pattern {
+    pub steps: Vec<Step>,
+    /// Times the steps play; 0 repeats until stopped.
+    #[serde(default = "once")]
+    pub repeat: u32,
+    /// Overrides the priority of the slot the pattern plays in. Files may not reach the
+    /// drop-off and stop alerts.
+    #[serde(default)]
+    pub priority: Option<u8>,
+}
+
+fn once() -> u32 {
+    1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternError {
+    NoSteps,
+    /// Node states are 1..=4, or 0 for the live frame.
+    BadState {
+        step: usize,
+        node: usize,
+        state: u8,
+    },
+    TooShort {
+        step: usize,
+        ms: u64,
+    },
+    TooLong {
+        step: usize,
+        ms: u64,
+    },
+    TooManyRepeats(u32),
+    /// Would outrank the drop-off and stop alerts.
+    Priority(u8),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::NoSteps => write!(f, "pattern has no steps"),
+            PatternError::BadState { step, node, state } => {
+                write!(f, "step {step}: node {node} has state {state}, expected 0..=4")
+            }
+            PatternError::TooShort { step, ms } => {
+                write!(f, "step {step} lasts {ms} ms, at least {} needed", MIN_STEP.as_millis())
+            }
+            PatternError::TooLong { step, ms } => {
+                write!(f, "step {step} lasts {ms} ms, at most {} allowed", MAX_STEP.as_millis())
+            }
+            PatternError::TooManyRepeats(repeat) => write!(f, "repeats {repeat} times, at most {MAX_REPEAT} allowed"),
+            PatternError::Priority(priority) => {
+                write!(f, "priority {priority} must be below {}", Slot::DropOff.priority())
+            }
+        }
+    }
+}
+
+impl Pattern {
+    fn on(nodes: &[usize], state: u8, ms: u64) -> Step {
+        let mut states = [None; NODE_COUNT];
+        for &n in nodes.iter().filter(|n| **n < NODE_COUNT) {
+            states[n] = Some(state);
+        }
+        Step { states, ms }
+    }
+
+    fn new(steps: Vec<Step>, repeat: u32) -> Self {
+        Self {
+            steps,
+            repeat,
+            priority: None,
+        }
+    }
+
+    /// `nodes` alternate between the two states, `ms` each, until stopped.
+    pub fn pulse(nodes: &[usize], states: [u8; 2], ms: u64) -> Self {
+        Self::new(states.iter().map(|s| Self::on(nodes, *s, ms)).collect(), 0)
+    }
+
+    /// `nodes` take `state` one after another, once; a sweep to the right is a right turn.
+    pub fn sweep(nodes: &[usize], state: u8, ms: u64) -> Self {
+        Self::new(nodes.iter().map(|n| Self::on(&[*n], state, ms)).collect(), 1)
+    }
+
+    /// `nodes` swell from the softest state to the firmest and back, `ms` per level.
+    pub fn throb(nodes: &[usize], ms: u64) -> Self {
+        Self::new([4, 3, 2, 1, 2, 3].iter().map(|s| Self::on(nodes, *s, ms)).collect(), 0)
+    }
+
+    /// Two taps of `states[0]` on `nodes`, `states[1]` between them, then a rest of `gap_ms`.
+    pub fn double_tap(nodes: &[usize], states: [u8; 2], tap_ms: u64, gap_ms: u64) -> Self {
+        let [tap, rest] = states;
+        let steps = vec![
+            Self::on(nodes, tap, tap_ms),
+            Self::on(nodes, rest, tap_ms),
+            Self::on(nodes, tap, tap_ms),
+            Self::on(nodes, rest, gap_ms),
+        ];
+        Self::new(steps, 0)
+    }
+
+    /// Whole-belt frames shown in turn, `ms` each, `repeat` times (0 for ever).
+    pub fn frames(frames: &[[u8; NODE_COUNT]], ms: u64, repeat: u32) -> Self {
+        let steps = frames
+            .iter()
+            .map(|f| Step {
+                states: f.map(Some),
+                ms,
+            })
+            .collect();
+        Self::new(steps, repeat)
+    }
+
+    /// Checks a pattern from a file; the built-in ones clamp instead.
+    pub fn validate(&self) -> Result<(), PatternError> {
+        if self.steps.is_empty() {
+            return Err(PatternError::NoSteps);
+        }
+        if self.repeat > MAX_REPEAT {
+            return Err(PatternError::TooManyRepeats(self.repeat));
+        }
+        if let Some(priority) = self.priority.filter(|p| *p >= Slot::DropOff.priority()) {
+            return Err(PatternError::Priority(priority));
+        }
+        for (step, s) in self.steps.iter().enumerate() {
+            if Duration::from_millis(s.ms) < MIN_STEP {
+                return Err(PatternError::TooShort { step, ms: s.ms });
+            }
+            if Duration::from_millis(s.ms) > MAX_STEP {
+                return Err(PatternError::TooLong { step, ms: s.ms });
+            }
+            for (node, state) in s.states.iter().enumerate() {
+                if let Some(state) = state.filter(|s| !(1..=4).contains(s)) {
+                    return Err(PatternError::BadState { step, node, state });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saturates rather than overflowing on built-in patterns, which are not validated.
+    pub fn duration(&self) -> Duration {
+        self.steps
+            .iter()
+            .map(Step::duration)
+            .fold(Duration::ZERO, Duration::saturating_add)
+    }
+
+    /// The step playing `elapsed` after the start and how long until it ends, or `None`
+    /// once it has played `repeat` times.
+    fn step_at(&self, elapsed: Duration) -> Option<(&Step, Duration)> {
+        let total = self.duration();
+        let end = total.checked_mul(self.repeat).unwrap_or(Duration::MAX);
+        if total.is_zero() || (self.repeat > 0 && elapsed >= end) {
+            return None;
+        }
+        let mut t = Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64);
+        for step in &self.steps {
+            if t < step.duration() {
+                return Some((step, step.duration() - t));
+            }
+            t -= step.duration();
+        }
+        None
+    }
+}
+
+/// Where an effect plays; a slot holds one pattern at a time and higher slots win.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Slot {
+    /// Nodes without data.
+    Unknown,
+    /// Cues such as turn sweeps and patterns played by name.
+    Cue,
+    DropOff,
+    Stop,
+}
+
+impl Slot {
+    /// Patterns without their own priority play at their slot's.
+    pub fn priority(self) -> u8 {
+        match self {
+            Slot::Unknown => 10,
+            Slot::Cue => 20,
+            Slot::DropOff => 30,
+            Slot::Stop => 40,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Playing {
+    slot: Slot,
+    pattern: Pattern,
+    started: Instant,
+}
+
+impl Playing {
+    fn priority(&self) -> u8 {
+        self.pattern.priority.unwrap_or(self.slot.priority())
+    }
+}
+
+/// Named patterns from a file, checked when loaded.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct PatternLibrary {
+    patterns: BTreeMap<String, Pattern>,
+}
+
+impl PatternLibrary {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// Reads and checks every pattern; the error names the first bad one.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let library: Self = serde_json::from_slice(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for (name, pattern) in &library.patterns {
+            pattern
+                .validate()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("pattern `{name}`: {e}")))?;
+        }
+        Ok(library)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Pattern> {
+        self.patterns.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.patterns.keys().map(String::as_str)
+    }
+}
+
+/// The live node states with whatever effects are playing on top.
+#[derive(Clone, Debug)]
+pub struct PatternEngine {
+    live: [u8; NODE_COUNT],
+    playing: Vec<Playing>,
+}
+
+impl PatternEngine {
+    /// `base` shows until the first live frame, e.g. the safe state.
+    pub fn new(base: [u8; NODE_COUNT]) -> Self {
+        Self {
+            live: base,
+            playing: Vec::new(),
+        }
+    }
+
+    pub fn set_live(&mut self, states: [u8; NODE_COUNT]) {
+        self.live = states;
+    }
+
+    /// Starts `pattern` in `slot` from its first step, replacing what the slot was playing.
+    pub fn play(&mut self, slot: Slot, pattern: Pattern, now: Instant) {
+        self.stop(slot);
+        self.playing.push(Playing {
+            slot,
+            pattern,
+            started: now,
+        });
+        self.playing.sort_by_key(Playing::priority);
+    }
+
+    /// Like `play`, but leaves the slot alone if it is already playing `pattern`, so an
+    /// effect re-requested every frame keeps its rhythm.
+    pub fn ensure(&mut self, slot: Slot, pattern: Pattern, now: Instant) {
+        if !self.playing.iter().any(|p| p.slot == slot && p.pattern == pattern) {
+            self.play(slot, pattern, now);
+        }
+    }
+
+    /// Returns whether the slot was playing.
+    pub fn stop(&mut self, slot: Slot) -> bool {
+        let before = self.playing.len();
+        self.playing.retain(|p| p.slot != slot);
+        self.playing.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.playing.clear();
+    }
+
+    pub fn is_playing(&self, slot: Slot) -> bool {
+        self.playing.iter().any(|p| p.slot == slot)
+    }
+
+    /// The live states with every effect laid over them, lowest priority first. Finished
+    /// one-shots are dropped.
+    pub fn render(&mut self, now: Instant) -> [u8; NODE_COUNT] {
+        self.playing
+            .retain(|p| p.pattern.step_at(now.saturating_duration_since(p.started)).is_some());
+        let mut out = self.live;
+        for p in &self.playing {
+            if let Some((step, _)) = p.pattern.step_at(now.saturating_duration_since(p.started)) {
+                for (node, state) in out.iter_mut().zip(step.states) {
+                    if let Some(s) = state {
+                        *node = s;
+                    }
+                }
+            }
+        }
+        out.map(|s| s.clamp(1, 4))
+    }
+
+    /// When a playing effect next moves to another step, if any is playing. Never more than
+    /// `MAX_STEP` ahead, so a huge built-in step cannot overflow the clock; waking early is harmless.
+    pub fn next_change(&self, now: Instant) -> Option<Instant> {
+        self.playing
+            .iter()
+            .map(|p| {
+                p.pattern
+                    .step_at(now.saturating_duration_since(p.started))
+                    .map_or(Duration::ZERO, |(_, left)| left)
+            })
+            .min()
+            .map(|left| now + left.min(MAX_STEP))
+    }
+}
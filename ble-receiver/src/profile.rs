@@ -0,0 +1,142 @@
+//! Named wearer profiles: how sensitive the mapping is and how firm each node may get, since
+//! one wearer finds the firmest state painful while another cannot feel the softer ones.
+//!
+//! Profiles live in a JSON file on the Pi, which also records the active one so it survives a
+//! restart:
+//!
+//! ```json
+//! {
+//!   "active": "sam",
+//!   "profiles": {
+//!     "sam": { "depth": { "near_m": 0.3, "far_m": 3.0 }, "max_level": [2, 2, 2, 2, 2, 2] },
+//!     "alex": { "thresholds": [0.1, 0.3, 0.5], "history": 3 }
+//!   }
+//! }
+//! ```
+
+use crate::{
+    calibration::NodeCalibration,
+    mapping::{DepthRange, GridPolicy},
+    NODE_COUNT,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    /// Proximity at which a node steps up to states 3, 2 and 1.
+    pub thresholds: [f32; 3],
+    pub depth: DepthRange,
+    /// Frames each node's value is averaged over.
+    pub history: usize,
+    /// Nodes the wearer does not want are held empty.
+    pub enabled: [bool; NODE_COUNT],
+    /// Firmest level per node, 0 (empty) to 3 (state 1), as in the calibration table.
+    pub max_level: [u8; NODE_COUNT],
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            thresholds: [0.25, 0.5, 0.75],
+            depth: DepthRange::default(),
+            history: 1,
+            enabled: [true; NODE_COUNT],
+            max_level: [3; NODE_COUNT],
+        }
+    }
+}
+
+impl Profile {
+    /// Copies the mapping tunables over the configured ones.
+    pub fn tune(&self, grid: &mut GridPolicy, depth: &mut DepthRange) {
+        grid.thresholds = self.thresholds;
+        grid.history = self.history;
+        *depth = self.depth;
+    }
+
+    /// Holds disabled nodes empty and caps the rest at their level.
+    pub fn limit(&self, states: &[u8; NODE_COUNT]) -> [u8; NODE_COUNT] {
+        std::array::from_fn(|idx| {
+            NodeCalibration {
+                disabled: !self.enabled[idx],
+                max_level: self.max_level[idx],
+                ..Default::default()
+            }
+            .apply(states[idx])
+        })
+    }
+}
+
+/// The profile file and which profile is active.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfileStore {
+    active: Option<String>,
+    profiles: BTreeMap<String, Profile>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl ProfileStore {
+    /// A missing file is an empty store.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut store: Self = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e),
+        };
+        if let Some(name) = store.active.as_deref().filter(|n| !store.profiles.contains_key(*n)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("active profile {name:?} is not defined"),
+            ));
+        }
+        store.path = Some(path.to_path_buf());
+        Ok(store)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn active_name(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub fn active(&self) -> Option<&Profile> {
+        self.profiles.get(self.active.as_deref()?)
+    }
+
+    /// Makes `name` active; `None` if there is no such profile.
+    pub fn select(&mut self, name: &str) -> Option<Profile> {
+        let profile = *self.profiles.get(name)?;
+        self.active = Some(name.to_string());
+        Some(profile)
+    }
+
+    /// Writes the store back to the file it was loaded from, so the active profile persists.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// What the profile characteristic reads, e.g. `{"active":"sam","profiles":["alex","sam"]}`.
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "active": self.active,
+            "profiles": self.names().collect::<Vec<_>>(),
+        })
+        .to_string()
+    }
+}
@@ -0,0 +1,33 @@
+use std::{io, time::Duration};
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Teardown finished and the belt was left in its safe state.
+pub const EXIT_OK: u8 = 0;
+/// Teardown finished but the safe state could not be confirmed on the serial link.
+pub const EXIT_SAFE_STATE_FAILED: u8 = 1;
+/// Bad command line, e.g. `--profile` with a name the profile file does not have.
+pub const EXIT_USAGE: u8 = 2;
+
+/// Upper bound on how long the worker may take to drive the safe state before we give up.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// bluer unregisters the advertisement and GATT application in tasks spawned when their handles
+/// drop; the runtime has to keep running this long for them to reach BlueZ.
+pub const UNREGISTER_GRACE: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+/// Resolves on the first SIGINT or SIGTERM.
+pub async fn wait_for_signal() -> io::Result<Signal> {
+    let mut term = signal(SignalKind::terminate())?;
+    let mut int = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = term.recv() => Ok(Signal::Terminate),
+        _ = int.recv() => Ok(Signal::Interrupt),
+    }
+}
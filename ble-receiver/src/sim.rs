@@ -0,0 +1,201 @@
+use crate::NODE_COUNT;
+use nix::{
+    pty::openpty,
+    sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg},
+    unistd::ttyname,
+};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Valve pins `(pin1, pin2)` for a node state, exactly as `apply_node_state` in
+/// `hardware/code.py` sets them. Anything outside 1..=4 falls through to the default branch,
+/// which happens to match state 1.
+pub fn apply_node_state(state: u8) -> (bool, bool) {
+    match state {
+        1 => (false, false),
+        2 => (false, true),
+        3 => (true, false),
+        4 => (true, true),
+        _ => (false, false),
+    }
+}
+
+/// Pressure (0 = empty, 1 = full) a node settles at for a pin pair. The firmware only knows
+/// pins; this is our model of the bladders, chosen so state 1 (nearest) is fully inflated and
+/// state 4 (farthest) is empty, matching `grid_to_node_states_4`.
+pub fn target_pressure(pins: (bool, bool)) -> f32 {
+    match pins {
+        (false, false) => 1.0,
+        (false, true) => 2.0 / 3.0,
+        (true, false) => 1.0 / 3.0,
+        (true, true) => 0.0,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SimParams {
+    /// Full-scale pressure change per second while filling.
+    pub inflate_per_s: f32,
+    /// Full-scale pressure change per second while venting.
+    pub deflate_per_s: f32,
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self {
+            inflate_per_s: 1.5,
+            deflate_per_s: 2.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct NodeSim {
+    pins: (bool, bool),
+    from: f32,
+    since: Instant,
+}
+
+impl NodeSim {
+    fn pressure(&self, now: Instant, p: &SimParams) -> f32 {
+        let target = target_pressure(self.pins);
+        let dt = now.saturating_duration_since(self.since).as_secs_f32();
+        if target > self.from {
+            (self.from + p.inflate_per_s * dt).min(target)
+        } else {
+            (self.from - p.deflate_per_s * dt).max(target)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BeltSnapshot {
+    pub frames: u64,
+    pub states: [u8; NODE_COUNT],
+    pub pins: [(bool, bool); NODE_COUNT],
+    pub pressure: [f32; NODE_COUNT],
+}
+
+/// The Feather plus bladders: consumes the serial byte stream six bytes at a time like
+/// `code.py` and tracks pressure over time.
+#[derive(Debug)]
+pub struct VirtualBelt {
+    params: SimParams,
+    nodes: [NodeSim; NODE_COUNT],
+    states: [u8; NODE_COUNT],
+    buf: Vec<u8>,
+    frames: u64,
+}
+
+impl VirtualBelt {
+    /// Starts with every pin low, like the Feather after boot, and empty bladders.
+    pub fn new(params: SimParams, now: Instant) -> Self {
+        Self {
+            params,
+            nodes: [NodeSim {
+                pins: (false, false),
+                from: 0.0,
+                since: now,
+            }; NODE_COUNT],
+            states: [0; NODE_COUNT],
+            buf: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8], now: Instant) {
+        self.buf.extend_from_slice(bytes);
+        while self.buf.len() >= NODE_COUNT {
+            let frame: Vec<u8> = self.buf.drain(..NODE_COUNT).collect();
+            self.apply_frame(&frame, now);
+        }
+    }
+
+    fn apply_frame(&mut self, frame: &[u8], now: Instant) {
+        for (i, &s) in frame.iter().enumerate().take(NODE_COUNT) {
+            let node = &mut self.nodes[i];
+            node.from = node.pressure(now, &self.params);
+            node.since = now;
+            node.pins = apply_node_state(s);
+            self.states[i] = s;
+        }
+        self.frames += 1;
+    }
+
+    pub fn snapshot(&self, now: Instant) -> BeltSnapshot {
+        BeltSnapshot {
+            frames: self.frames,
+            states: self.states,
+            pins: self.nodes.map(|n| n.pins),
+            pressure: self.nodes.map(|n| n.pressure(now, &self.params)),
+        }
+    }
+}
+
+/// A [`VirtualBelt`] behind a pseudo-terminal; open [`PtySim::path`] as the serial device.
+pub struct PtySim {
+    path: PathBuf,
+    belt: Arc<Mutex<VirtualBelt>>,
+    _slave: OwnedFd,
+}
+
+impl PtySim {
+    pub fn spawn(params: SimParams) -> io::Result<Self> {
+        let pty = openpty(None, None)?;
+
+        let mut t = tcgetattr(&pty.slave)?;
+        cfmakeraw(&mut t);
+        tcsetattr(&pty.slave, SetArg::TCSANOW, &t)?;
+        let path = ttyname(&pty.slave)?;
+
+        let belt = Arc::new(Mutex::new(VirtualBelt::new(params, Instant::now())));
+        let belt_for_reader = Arc::clone(&belt);
+        let mut master = File::from(pty.master);
+
+        std::thread::Builder::new().name("belt-sim".to_string()).spawn(move || {
+            let mut buf = [0u8; 256];
+            while let Ok(n) = master.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                if let Ok(mut belt) = belt_for_reader.lock() {
+                    belt.feed(&buf[..n], Instant::now());
+                }
+            }
+        })?;
+
+        Ok(Self {
+            path,
+            belt,
+            _slave: pty.slave,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn snapshot(&self) -> BeltSnapshot {
+        let belt = self.belt.lock().unwrap_or_else(|e| e.into_inner());
+        belt.snapshot(Instant::now())
+    }
+
+    /// Polls until at least `frames` frames have been decoded; false on timeout.
+    pub fn wait_for_frames(&self, frames: u64, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if self.snapshot().frames >= frames {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        self.snapshot().frames >= frames
+    }
+}
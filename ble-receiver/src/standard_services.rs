@@ -0,0 +1,116 @@
+use crate::{battery::BatterySource, config::DeviceInfo};
+use bluer::{
+    gatt::local::{
+        Characteristic, CharacteristicNotify, CharacteristicNotifyMethod, CharacteristicRead, ReqError, Service,
+    },
+    Uuid,
+};
+use futures::FutureExt;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::warn;
+
+pub const DEVICE_INFO_UUID: Uuid = Uuid::from_u128(0x0000180a_0000_1000_8000_00805f9b34fb);
+pub const MANUFACTURER_NAME_UUID: Uuid = Uuid::from_u128(0x00002a29_0000_1000_8000_00805f9b34fb);
+pub const MODEL_NUMBER_UUID: Uuid = Uuid::from_u128(0x00002a24_0000_1000_8000_00805f9b34fb);
+pub const SERIAL_NUMBER_UUID: Uuid = Uuid::from_u128(0x00002a25_0000_1000_8000_00805f9b34fb);
+pub const FIRMWARE_REVISION_UUID: Uuid = Uuid::from_u128(0x00002a26_0000_1000_8000_00805f9b34fb);
+
+pub const BATTERY_UUID: Uuid = Uuid::from_u128(0x0000180f_0000_1000_8000_00805f9b34fb);
+pub const BATTERY_LEVEL_UUID: Uuid = Uuid::from_u128(0x00002a19_0000_1000_8000_00805f9b34fb);
+
+const BATTERY_POLL: Duration = Duration::from_secs(30);
+
+fn fixed_string(uuid: Uuid, value: String) -> Characteristic {
+    Characteristic {
+        uuid,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |_req| {
+                let value = value.clone().into_bytes();
+                async move { Ok(value) }.boxed()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+pub fn device_information_service(info: &DeviceInfo, unit_id: u16) -> Service {
+    let serial = info.serial.clone().unwrap_or_else(|| format!("WHV-{unit_id:04X}"));
+
+    Service {
+        uuid: DEVICE_INFO_UUID,
+        primary: true,
+        characteristics: vec![
+            fixed_string(MANUFACTURER_NAME_UUID, info.manufacturer.clone()),
+            fixed_string(MODEL_NUMBER_UUID, info.model.clone()),
+            fixed_string(SERIAL_NUMBER_UUID, serial),
+            fixed_string(FIRMWARE_REVISION_UUID, env!("CARGO_PKG_VERSION").to_string()),
+        ],
+        ..Default::default()
+    }
+}
+
+/// Value of the Battery Level characteristic: one byte, 0..=100.
+pub fn battery_level_value(source: &BatterySource) -> Result<Vec<u8>, ReqError> {
+    source.level().map(|v| vec![v]).map_err(|e| {
+        warn!("Battery level unavailable: {e}");
+        ReqError::Failed
+    })
+}
+
+/// `battery_level_value` on the blocking pool, since file and sysfs sources read from disk.
+async fn read_battery_level(source: BatterySource) -> Result<Vec<u8>, ReqError> {
+    tokio::task::spawn_blocking(move || battery_level_value(&source))
+        .await
+        .unwrap_or(Err(ReqError::Failed))
+}
+
+/// Battery Service whose level is read from `source` on every read and polled for notifications.
+pub fn battery_service(source: BatterySource) -> Service {
+    let source_for_read = source.clone();
+
+    Service {
+        uuid: BATTERY_UUID,
+        primary: true,
+        characteristics: vec![Characteristic {
+            uuid: BATTERY_LEVEL_UUID,
+            read: Some(CharacteristicRead {
+                read: true,
+                fun: Box::new(move |_req| {
+                    let source = source_for_read.clone();
+                    read_battery_level(source).boxed()
+                }),
+                ..Default::default()
+            }),
+            notify: Some(CharacteristicNotify {
+                notify: true,
+                method: CharacteristicNotifyMethod::Fun(Box::new(move |mut notifier| {
+                    let source = source.clone();
+                    async move {
+                        let mut last = None;
+                        loop {
+                            if let Ok(level) = read_battery_level(source.clone()).await {
+                                if last.as_ref() != Some(&level) {
+                                    if notifier.notify(level.clone()).await.is_err() {
+                                        break;
+                                    }
+                                    last = Some(level);
+                                }
+                            }
+                            tokio::select! {
+                                _ = sleep(BATTERY_POLL) => {}
+                                _ = notifier.stopped() => break,
+                            }
+                        }
+                    }
+                    .boxed()
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
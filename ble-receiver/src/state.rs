@@ -0,0 +1,107 @@
+use crate::{
+    arbitration::Arbiter,
+    connection::{LinkEvent, Links},
+    profile::ProfileStore,
+    NODE_COUNT,
+};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+pub const HISTORY_MAX: usize = 8;
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+pub struct GridFrame {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<Vec<f32>>,
+    pub received: Instant,
+}
+
+impl GridFrame {
+    pub fn new(data: Vec<Vec<f32>>, received: Instant) -> Self {
+        Self {
+            rows: data.len(),
+            cols: data.first().map(|r| r.len()).unwrap_or(0),
+            data,
+            received,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkDown {
+    pub was_owner: bool,
+    /// The owner left, or nobody is left: the belt goes to its safe state.
+    pub safe_state: bool,
+}
+
+/// Everything the GATT handlers, the worker and the live views share.
+#[derive(Default)]
+pub struct AppState {
+    pub last_raw: Vec<u8>,
+    pub last_grid: Option<GridFrame>,
+    pub history: VecDeque<GridFrame>,
+    pub last_states: Option<[u8; NODE_COUNT]>,
+    pub frame_times: VecDeque<Instant>,
+    pub arbiter: Arbiter,
+    pub links: Links,
+    pub profiles: ProfileStore,
+    pub serial_ok: bool,
+    pub shutting_down: bool,
+}
+
+impl AppState {
+    pub fn push_grid(&mut self, gf: GridFrame) {
+        self.last_grid = Some(gf.clone());
+        self.history.push_back(gf);
+        while self.history.len() > HISTORY_MAX {
+            self.history.pop_front();
+        }
+    }
+
+    /// Remembers what was last sent to the belt, for the info string and live views.
+    pub fn push_states(&mut self, states: &[u8], now: Instant) {
+        let mut s = [0u8; NODE_COUNT];
+        let n = states.len().min(NODE_COUNT);
+        s[..n].copy_from_slice(&states[..n]);
+        self.last_states = Some(s);
+
+        self.frame_times.push_back(now);
+        while self
+            .frame_times
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) > RATE_WINDOW)
+        {
+            self.frame_times.pop_front();
+        }
+    }
+
+    /// Records a link event; a disconnect also releases control if the central held it. Returns
+    /// what a link that went down means for the belt.
+    pub fn link_event(&mut self, evt: LinkEvent, now: Instant) -> Option<LinkDown> {
+        let went_down = self.links.apply(evt, now);
+        match evt {
+            LinkEvent::Disconnected(addr) if went_down => {
+                let was_owner = self.arbiter.release(addr);
+                Some(LinkDown {
+                    was_owner,
+                    safe_state: was_owner || self.links.connected_count() == 0,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Frames per second sent to the belt over the last couple of seconds.
+    pub fn frame_rate(&self, now: Instant) -> f32 {
+        let recent = self
+            .frame_times
+            .iter()
+            .filter(|t| now.saturating_duration_since(**t) <= RATE_WINDOW)
+            .count();
+        recent as f32 / RATE_WINDOW.as_secs_f32()
+    }
+}
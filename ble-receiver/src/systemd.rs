@@ -0,0 +1,115 @@
+use std::{
+    io,
+    os::{linux::net::SocketAddrExt, unix::net::SocketAddr, unix::net::UnixDatagram},
+    path::Path,
+    time::Duration,
+};
+
+pub const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sends `sd_notify(3)` datagrams to the service manager.
+pub struct Notifier {
+    sock: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    /// `None` when we were not started by systemd with `Type=notify`.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        match std::env::var_os(NOTIFY_SOCKET_ENV) {
+            Some(path) => Self::connect(path.to_string_lossy().as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Accepts a filesystem path or an abstract socket name starting with `@`.
+    pub fn connect(path: &str) -> io::Result<Self> {
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Self {
+            sock: UnixDatagram::unbound()?,
+            addr,
+        })
+    }
+
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.sock.send_to_addr(state.as_bytes(), &self.addr)?;
+        Ok(())
+    }
+
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    pub fn status(&self, text: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={text}"))
+    }
+}
+
+/// How often to ping, i.e. a third of `WatchdogSec=`, or `None` if the watchdog is off or meant
+/// for another process. A ping can wait up to one interval more for the worker's answer, so even
+/// a late one lands inside the watchdog period.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = std::env::var("WATCHDOG_PID").ok().and_then(|p| p.parse::<u32>().ok()) {
+        if pid != std::process::id() {
+            return None;
+        }
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 3))
+}
+
+/// Writes entries with custom fields using the journald native protocol.
+pub struct Journal {
+    sock: UnixDatagram,
+}
+
+impl Journal {
+    /// `None` unless stderr is connected to the journal, so plain terminal runs stay quiet.
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os("JOURNAL_STREAM")?;
+        Self::connect(Path::new(JOURNAL_SOCKET)).ok()
+    }
+
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        let sock = UnixDatagram::unbound()?;
+        sock.connect(path)?;
+        Ok(Self { sock })
+    }
+
+    /// Field names must be upper-case ASCII, digits and underscores, as journald requires.
+    pub fn send(&self, priority: u8, message: &str, fields: &[(&str, String)]) -> io::Result<()> {
+        let mut buf = Vec::new();
+        append_field(&mut buf, "MESSAGE", message);
+        append_field(&mut buf, "PRIORITY", &priority.to_string());
+        append_field(&mut buf, "SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME"));
+        for (k, v) in fields {
+            append_field(&mut buf, k, v);
+        }
+        self.sock.send(&buf)?;
+        Ok(())
+    }
+}
+
+fn append_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
@@ -0,0 +1,183 @@
+use crate::{
+    state::{AppState, GridFrame},
+    NODE_COUNT,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, Paragraph},
+    Frame,
+};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Mutex, time::sleep};
+
+const REFRESH: Duration = Duration::from_millis(100);
+
+/// What one redraw needs, copied out so the state lock is not held while drawing.
+struct Snapshot {
+    now: Instant,
+    grid: Option<GridFrame>,
+    states: Option<[u8; NODE_COUNT]>,
+    history: Vec<GridFrame>,
+    fps: f32,
+    serial_ok: bool,
+    owner: String,
+    connected: usize,
+}
+
+impl Snapshot {
+    fn take(st: &AppState, now: Instant) -> Self {
+        Self {
+            now,
+            grid: st.last_grid.clone(),
+            states: st.last_states,
+            history: st.history.iter().rev().cloned().collect(),
+            fps: st.frame_rate(now),
+            serial_ok: st.serial_ok,
+            owner: st
+                .arbiter
+                .owner()
+                .map(|a| a.to_string())
+                .unwrap_or_else(|| "none".to_string()),
+            connected: st.links.connected_count(),
+        }
+    }
+}
+
+/// Hands the terminal back however `run` ends, including when its future is dropped because a
+/// signal won the race in `main`.
+struct Restore;
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+/// Takes over the terminal and redraws from `state` until `q` or Esc is pressed.
+pub async fn run(state: Arc<Mutex<AppState>>) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let _restore = Restore;
+
+    loop {
+        let snap = Snapshot::take(&*state.lock().await, Instant::now());
+        terminal.draw(|f| draw(f, &snap))?;
+
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(k) = event::read()? {
+                if k.kind == KeyEventKind::Press && matches!(k.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+        sleep(REFRESH).await;
+    }
+}
+
+fn draw(f: &mut Frame, s: &Snapshot) {
+    let rows = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(NODE_COUNT as u16 + 2),
+        Constraint::Length(s.history.len().max(1) as u16 + 2),
+    ])
+    .split(f.area());
+    let mid = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).split(rows[1]);
+
+    let serial = if s.serial_ok {
+        Span::styled("serial OK", Style::default().fg(Color::Green))
+    } else {
+        Span::styled("serial ERROR", Style::default().fg(Color::Red))
+    };
+    let status = Line::from(vec![
+        Span::raw(format!("{:>5.1} fps   ", s.fps)),
+        serial,
+        Span::raw(format!("   owner={}   connected={}   (q to quit)", s.owner, s.connected)),
+    ]);
+    f.render_widget(Paragraph::new(status).block(titled("WHV receiver")), rows[0]);
+
+    f.render_widget(heatmap(s.grid.as_ref()), mid[0]);
+    f.render_widget(waistband(s.states), mid[1]);
+    f.render_widget(history(&s.history, s.now), rows[2]);
+}
+
+fn titled(title: impl Into<String>) -> Block<'static> {
+    Block::default().borders(Borders::ALL).title(title.into())
+}
+
+/// Dark for far (0.0), red for near (1.0), grey for missing values.
+fn heat(v: f32) -> Color {
+    if !v.is_finite() {
+        return Color::DarkGray;
+    }
+    let v = v.clamp(0.0, 1.0);
+    Color::Rgb((v * 255.0) as u8, ((1.0 - v) * 60.0) as u8, ((1.0 - v) * 110.0) as u8)
+}
+
+fn heatmap(grid: Option<&GridFrame>) -> Paragraph<'static> {
+    let Some(grid) = grid else {
+        return Paragraph::new("no grid yet").block(titled("Latest grid"));
+    };
+
+    let lines: Vec<Line> = grid
+        .data
+        .iter()
+        .map(|row| {
+            Line::from(
+                row.iter()
+                    .map(|v| Span::styled(format!(" {v:>4.2} "), Style::default().fg(Color::White).bg(heat(*v))))
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+
+    Paragraph::new(lines).block(titled(format!("Latest grid {}x{}", grid.rows, grid.cols)))
+}
+
+/// One bar per node; state 1 (nearest) is the fullest bar, 4 (farthest) the emptiest.
+fn waistband(states: Option<[u8; NODE_COUNT]>) -> Paragraph<'static> {
+    let Some(states) = states else {
+        return Paragraph::new("nothing sent yet").block(titled("Waistband"));
+    };
+
+    let lines: Vec<Line> = states
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let level = if (1..=4).contains(s) { 5 - *s as usize } else { 0 };
+            let color = match level {
+                4 => Color::Red,
+                3 => Color::LightRed,
+                2 => Color::Yellow,
+                1 => Color::Green,
+                _ => Color::DarkGray,
+            };
+            Line::from(vec![
+                Span::raw(format!("N{} ", i + 1)),
+                Span::styled(format!("{:<4}", "█".repeat(level)), Style::default().fg(color)),
+                Span::raw(format!(" {s}")),
+            ])
+        })
+        .collect();
+
+    Paragraph::new(lines).block(titled("Waistband"))
+}
+
+fn history(frames: &[GridFrame], now: Instant) -> List<'static> {
+    let items: Vec<ListItem> = frames
+        .iter()
+        .map(|g| {
+            let cells = g.data.iter().flatten().filter(|v| v.is_finite());
+            let (lo, hi) = cells.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+            let age = now.saturating_duration_since(g.received).as_secs_f32();
+            ListItem::new(format!("-{age:>5.1}s  {}x{}  min={lo:.2} max={hi:.2}", g.rows, g.cols))
+        })
+        .collect();
+
+    List::new(items).block(titled("History"))
+}
@@ -0,0 +1,91 @@
+use crate::NODE_COUNT;
+use serde::Deserialize;
+use std::fmt;
+
+/// What to do with a raw node state outside `1..=levels`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRange {
+    /// Drop the whole frame; the belt keeps its previous states.
+    #[default]
+    Reject,
+    /// Pull the value to the nearest valid level and forward the frame.
+    Clamp,
+}
+
+/// Checks applied to raw frames before they reach the Feather, which would otherwise treat
+/// any unknown byte like state 1.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct RawPolicy {
+    /// Valid states are `1..=levels`; `hardware/code.py` knows four.
+    pub levels: u8,
+    pub out_of_range: OutOfRange,
+}
+
+impl Default for RawPolicy {
+    fn default() -> Self {
+        Self {
+            levels: 4,
+            out_of_range: OutOfRange::Reject,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RawError {
+    /// Raw frames carry exactly one byte per node.
+    Length(usize),
+    /// First offending node under [`OutOfRange::Reject`], with how many values were bad.
+    OutOfRange { node: usize, value: u8, violations: usize },
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawError::Length(len) => write!(f, "expected {NODE_COUNT} bytes, got {len}"),
+            RawError::OutOfRange { node, value, violations } => write!(
+                f,
+                "node {} state {value} out of range ({violations} value(s) out of range)",
+                node + 1
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checked {
+    pub states: [u8; NODE_COUNT],
+    /// Out-of-range values that were clamped; 0 for a clean frame.
+    pub violations: usize,
+}
+
+impl RawPolicy {
+    pub fn check(&self, data: &[u8]) -> Result<Checked, RawError> {
+        let mut states: [u8; NODE_COUNT] = data.try_into().map_err(|_| RawError::Length(data.len()))?;
+        let levels = self.levels.max(1);
+
+        let bad = |s: &u8| !(1..=levels).contains(s);
+        let violations = states.iter().filter(|s| bad(s)).count();
+        if violations == 0 {
+            return Ok(Checked { states, violations });
+        }
+
+        match self.out_of_range {
+            OutOfRange::Reject => {
+                let node = states.iter().position(bad).unwrap_or_default();
+                Err(RawError::OutOfRange {
+                    node,
+                    value: states[node],
+                    violations,
+                })
+            }
+            OutOfRange::Clamp => {
+                for s in &mut states {
+                    *s = (*s).clamp(1, levels);
+                }
+                Ok(Checked { states, violations })
+            }
+        }
+    }
+}
@@ -0,0 +1,538 @@
+use crate::{
+    approach::ApproachTracker,
+    arbitration::WriteVerdict,
+    bearing::SectorMap,
+    calibration::{Calibration, NodeCalibration},
+    control::Command,
+    danger::{StopDetector, StopStatus},
+    dropoff::{DropOffAlert, DropOffHit},
+    frame::{hex_dump, looks_like_binary_grid, looks_like_json, parse_binary_grid, parse_json_grid, Grid, GridDecoder, Units},
+    mapping::{DepthRange, GridMapper, NodeValues},
+    metrics::{FrameFormat, Metrics, ParseFailure},
+    pattern::{PatternEngine, PatternLibrary, Slot},
+    profile::Profile,
+    state::{AppState, GridFrame},
+    systemd::Journal,
+    validation::{RawError, RawPolicy},
+    NODE_COUNT,
+};
+use bluer::{gatt::local::ReqError, Address};
+use std::{
+    io::{self, Write},
+    sync::{atomic::Ordering, Arc, Mutex as StdMutex},
+    time::Instant,
+};
+use tokio::{
+    sync::{mpsc, oneshot, watch, Mutex},
+    task::JoinHandle,
+    time::sleep_until,
+};
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
+
+/// Where node states go: the Feather's serial port, a pty, or a buffer in tests.
+pub type Sink = Arc<StdMutex<Box<dyn Write + Send>>>;
+
+pub enum WorkerMsg {
+    Payload { data: Vec<u8>, received: Instant },
+    /// A grid decoded off the BLE path, e.g. binned from a depth frame.
+    Grid {
+        format: FrameFormat,
+        grid: Grid,
+        /// Nearest depth in the danger zone, when the sender had more detail than the grid.
+        nearest_m: Option<f32>,
+        /// Set when a depth frame saw the ground fall away ahead.
+        drop_off: Option<DropOffHit>,
+        received: Instant,
+    },
+    SafeState,
+    /// Take the current heading as straight ahead.
+    Calibrate,
+    /// Play the named pattern from the library as a cue.
+    Play(String),
+    StopPattern,
+    /// Switch to a wearer profile and resend the current states with it.
+    Profile(Profile),
+    /// Replace one node's calibration and resend the current states with it.
+    NodeCalibration { node: usize, calibration: NodeCalibration },
+    /// Drive the safe state, flush the port and stop; the result says whether the belt got it.
+    Shutdown(oneshot::Sender<io::Result<()>>),
+    /// Answered as soon as the worker gets to it; used to gate systemd watchdog pings.
+    Ping(oneshot::Sender<()>),
+}
+
+/// Body of the `WR_CHAR_UUID` write closure: refuses writes while shutting down or from a
+/// central that does not hold control, otherwise queues the payload for the worker.
+pub async fn handle_write(
+    state: &Mutex<AppState>,
+    tx: &mpsc::UnboundedSender<WorkerMsg>,
+    from: Address,
+    data: Vec<u8>,
+) -> Result<(), ReqError> {
+    let verdict = {
+        let mut st = state.lock().await;
+        if st.shutting_down {
+            return Err(ReqError::Failed);
+        }
+        st.arbiter.check_write(from, Instant::now())
+    };
+    if let WriteVerdict::Rejected { owner } = verdict {
+        warn!("Ignoring {} bytes from {from}; control is owned by {owner}", data.len());
+        return Err(ReqError::NotPermitted);
+    }
+    let _ = tx.send(WorkerMsg::Payload {
+        data,
+        received: Instant::now(),
+    });
+    Ok(())
+}
+
+/// A command written to the control characteristic. Only the owner may change how the belt
+/// behaves; takeover and release are open to every central.
+pub async fn handle_command(
+    state: &Mutex<AppState>,
+    tx: &mpsc::UnboundedSender<WorkerMsg>,
+    from: Address,
+    cmd: Command,
+) -> Result<(), ReqError> {
+    let mut st = state.lock().await;
+    let msg = match cmd {
+        Command::Takeover => {
+            match st.arbiter.takeover(from, Instant::now()) {
+                Some(prev) => info!("{from} took control from {prev}"),
+                None => info!("{from} took control"),
+            }
+            return Ok(());
+        }
+        Command::Release => {
+            if st.arbiter.release(from) {
+                info!("{from} released control");
+            }
+            return Ok(());
+        }
+        Command::Calibrate => WorkerMsg::Calibrate,
+        Command::Play { ref name } => WorkerMsg::Play(name.clone()),
+        Command::StopPattern => WorkerMsg::StopPattern,
+        Command::NodeCalibration { node, calibration } => WorkerMsg::NodeCalibration { node, calibration },
+    };
+    if let WriteVerdict::Rejected { owner } = st.arbiter.check_command(from, Instant::now()) {
+        warn!("Ignoring {cmd:?} from {from}; control is owned by {owner}");
+        return Err(ReqError::NotAuthorized);
+    }
+    match cmd {
+        Command::Calibrate => info!("{from} calibrated the heading"),
+        Command::Play { name } => info!("{from} asked for pattern {name:?}"),
+        Command::NodeCalibration { node, .. } => info!("{from} recalibrated node {node}"),
+        _ => {}
+    }
+    let _ = tx.send(msg);
+    Ok(())
+}
+
+pub struct Worker {
+    pub state: Arc<Mutex<AppState>>,
+    pub sink: Sink,
+    pub safe_state: [u8; NODE_COUNT],
+    pub raw_policy: RawPolicy,
+    pub mapper: GridMapper,
+    /// Scales grids sent in meters to proximity.
+    pub depth: DepthRange,
+    pub sectors: SectorMap,
+    pub approach: ApproachTracker,
+    pub stop: StopDetector,
+    pub drop_off: DropOffAlert,
+    /// Live states plus the effects playing over them; everything sent to the belt goes through it.
+    pub patterns: PatternEngine,
+    /// Patterns that can be played by name.
+    pub library: PatternLibrary,
+    /// Per-node limits of the active profile; its mapping tunables are copied into `mapper` and `depth`.
+    pub profile: Profile,
+    /// Applied to everything written to the belt, after the profile's limits; logs, metrics and
+    /// the TUI see the states before either.
+    pub calibration: Calibration,
+    /// Latest stop status, for the notify characteristic and WebSocket replies.
+    pub stops: watch::Sender<StopStatus>,
+    pub journal: Option<Journal>,
+    pub metrics: Arc<Metrics>,
+}
+
+/// Runs until `rx` closes or a `Shutdown` has been handled.
+pub fn spawn_worker(mut rx: mpsc::UnboundedReceiver<WorkerMsg>, mut worker: Worker) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut seq: u64 = 0;
+
+        loop {
+            let next = worker.patterns.next_change(Instant::now());
+            let msg = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                _ = sleep_until(next.unwrap_or_else(Instant::now).into()), if next.is_some() => {
+                    let now = Instant::now();
+                    worker.update_alerts(now);
+                    let states = worker.patterns.render(now);
+                    worker.forward(&states).await;
+                    continue;
+                }
+            };
+
+            match msg {
+                WorkerMsg::Payload { data, received } => {
+                    seq += 1;
+                    let span = frame_span(seq, data.len());
+                    worker.handle_payload(seq, data, received).instrument(span).await;
+                }
+                WorkerMsg::Grid {
+                    format,
+                    grid,
+                    nearest_m,
+                    drop_off,
+                    received,
+                } => {
+                    seq += 1;
+                    let span = frame_span(seq, 0);
+                    span.record("format", format.as_str());
+                    worker.drop_off_seen(drop_off);
+                    worker.handle_grid(seq, format, grid, nearest_m, received).instrument(span).await;
+                }
+                WorkerMsg::SafeState => {
+                    worker.clear_stop();
+                    worker.patterns.clear();
+                    worker.patterns.set_live(worker.safe_state);
+                    info!("Driving safe state {:?}", worker.safe_state);
+                    worker.forward(&worker.safe_state).await;
+                }
+                WorkerMsg::Calibrate => {
+                    worker.sectors.calibrate();
+                    info!("Next heading is straight ahead");
+                }
+                WorkerMsg::Play(name) => worker.play(&name).await,
+                WorkerMsg::StopPattern => {
+                    if worker.patterns.stop(Slot::Cue) {
+                        info!("Stopped pattern");
+                        let states = worker.patterns.render(Instant::now());
+                        worker.forward(&states).await;
+                    }
+                }
+                WorkerMsg::Profile(profile) => {
+                    worker.use_profile(profile);
+                    info!(?profile, "Switched profile");
+                    let states = worker.patterns.render(Instant::now());
+                    worker.forward(&states).await;
+                }
+                WorkerMsg::NodeCalibration { node, calibration } => {
+                    let mut table = worker.calibration;
+                    let Some(slot) = table.nodes.get_mut(node) else {
+                        warn!("No node {node} to calibrate");
+                        continue;
+                    };
+                    *slot = calibration;
+                    if let Err(e) = table.validate() {
+                        warn!("Ignoring calibration for node {node}: {e}");
+                        continue;
+                    }
+                    worker.calibration = table;
+                    info!(?calibration, "Calibrated node {node}");
+                    let states = worker.patterns.render(Instant::now());
+                    worker.forward(&states).await;
+                }
+                WorkerMsg::Shutdown(done) => {
+                    info!("Driving safe state {:?} before exit", worker.safe_state);
+                    let res = write_sink(&worker.sink, &worker.output(&worker.safe_state));
+                    let _ = done.send(res);
+                    break;
+                }
+                WorkerMsg::Ping(pong) => {
+                    let _ = pong.send(());
+                }
+            }
+        }
+    })
+}
+
+impl Worker {
+    async fn handle_payload(&mut self, seq: u64, data: Vec<u8>, received: Instant) {
+        let span = Span::current();
+
+        {
+            let mut st = self.state.lock().await;
+            st.last_raw = data.clone();
+        }
+
+        debug!(hex = %hex_dump(&data), "RX {} bytes", data.len());
+
+        let decoder: Option<(FrameFormat, ParseFailure, GridDecoder)> = if looks_like_json(&data) {
+            Some((FrameFormat::Json, ParseFailure::Json, parse_json_grid))
+        } else if looks_like_binary_grid(&data) {
+            Some((FrameFormat::Binary, ParseFailure::Binary, parse_binary_grid))
+        } else {
+            None
+        };
+        if let Some((format, failure, decode)) = decoder {
+            span.record("format", format.as_str());
+
+            let t = Instant::now();
+            let parsed = info_span!("parse").in_scope(|| decode(&data));
+            span.record("parse_us", t.elapsed().as_micros() as u64);
+
+            match parsed {
+                Some(grid) => self.handle_grid(seq, format, grid, None, received).await,
+                None => {
+                    self.metrics.parse_failure(failure);
+                    warn!("{} payload is not a valid grid", format.as_str());
+                }
+            }
+            return;
+        }
+
+        self.clear_stop();
+        self.patterns.stop(Slot::Unknown);
+        span.record("format", FrameFormat::Raw.as_str());
+        match self.raw_policy.check(&data) {
+            Ok(checked) => {
+                if checked.violations > 0 {
+                    self.metrics.raw_state_violations.fetch_add(checked.violations as u64, Ordering::Relaxed);
+                    warn!("Clamped {} out-of-range node state(s)", checked.violations);
+                }
+                self.metrics.frame(FrameFormat::Raw);
+                let now = Instant::now();
+                self.update_alerts(now);
+                self.patterns.set_live(checked.states);
+                let states = self.patterns.render(now);
+                self.emit(seq, FrameFormat::Raw, &states, received).await;
+            }
+            Err(e) => {
+                let kind = match e {
+                    RawError::Length(_) => ParseFailure::Length,
+                    RawError::OutOfRange { violations, .. } => {
+                        self.metrics.raw_state_violations.fetch_add(violations as u64, Ordering::Relaxed);
+                        ParseFailure::OutOfRange
+                    }
+                };
+                self.metrics.parse_failure(kind);
+                warn!("Rejected raw frame: {e}");
+            }
+        }
+    }
+
+    async fn handle_grid(
+        &mut self,
+        seq: u64,
+        format: FrameFormat,
+        mut grid: Grid,
+        nearest_m: Option<f32>,
+        received: Instant,
+    ) {
+        let span = Span::current();
+        span.record("units", grid.units.as_str());
+        let nearest = nearest_m.or_else(|| self.stop.zone.nearest_in_grid(&grid, &self.depth));
+        self.check_stop(nearest);
+        let view = grid.view;
+        if grid.units == Units::Meters {
+            self.depth.to_proximity(&mut grid.cells);
+        }
+
+        self.metrics.frame(format);
+        let gf = GridFrame::new(grid.cells, received);
+        span.record("grid", format!("{}x{}", gf.rows, gf.cols));
+
+        let t = Instant::now();
+        let mapped = info_span!("map").in_scope(|| {
+            if self.sectors.config.enabled {
+                let cells = self.sectors.node_cells(&gf.data, &view, received);
+                self.mapper.map_with(&gf.data, |idx| cells[idx])
+            } else {
+                self.mapper.map(&gf.data)
+            }
+        });
+        span.record("map_us", t.elapsed().as_micros() as u64);
+        self.state.lock().await.push_grid(gf);
+
+        let values = match mapped {
+            Ok(m) => {
+                if m.invalid > 0 {
+                    self.metrics.invalid_cells.fetch_add(m.invalid as u64, Ordering::Relaxed);
+                    debug!(invalid = m.invalid, "Grid has invalid cells");
+                }
+                Some(m.values)
+            }
+            Err(e) => {
+                self.metrics.parse_failure(ParseFailure::Grid);
+                warn!("Rejected grid: {e}");
+                None
+            }
+        };
+
+        // A raised stop or drop-off still plays over a grid that was rejected.
+        let now = Instant::now();
+        let alerting = self.update_alerts(now);
+        if let Some(values) = values.map(|values| self.track_approach(&values, received)) {
+            self.patterns.set_live(self.mapper.states(&values));
+            match self.mapper.unknown_pulse(&values) {
+                Some(pulse) => self.patterns.ensure(Slot::Unknown, pulse, now),
+                None => {
+                    self.patterns.stop(Slot::Unknown);
+                }
+            }
+        } else if !alerting {
+            return;
+        }
+        let states = self.patterns.render(now);
+        self.emit(seq, format, &states, received).await;
+    }
+
+    /// Plays the stop and drop-off alerts while they hold and stops them after; true if either plays.
+    fn update_alerts(&mut self, now: Instant) -> bool {
+        let stopped = self.stop.is_stopped();
+        if stopped {
+            self.patterns.ensure(Slot::Stop, self.stop.zone.alert(), now);
+        } else {
+            self.patterns.stop(Slot::Stop);
+        }
+        let dropping = self.drop_off.is_active(now);
+        if dropping {
+            self.patterns.ensure(Slot::DropOff, self.drop_off.config.alert(), now);
+        } else {
+            self.patterns.stop(Slot::DropOff);
+        }
+        stopped || dropping
+    }
+
+    fn use_profile(&mut self, profile: Profile) {
+        profile.tune(&mut self.mapper.policy, &mut self.depth);
+        self.profile = profile;
+    }
+
+    /// What the physical outputs are sent for `states`.
+    fn output(&self, states: &[u8; NODE_COUNT]) -> [u8; NODE_COUNT] {
+        self.calibration.apply(&self.profile.limit(states))
+    }
+
+    async fn play(&mut self, name: &str) {
+        let Some(pattern) = self.library.get(name) else {
+            warn!("No pattern named {name:?}");
+            return;
+        };
+        info!("Playing pattern {name:?}");
+        let now = Instant::now();
+        self.patterns.play(Slot::Cue, pattern.clone(), now);
+        let states = self.patterns.render(now);
+        self.forward(&states).await;
+    }
+
+    /// Updates time-to-collision from this frame and boosts the nodes something is closing in on.
+    fn track_approach(&mut self, values: &NodeValues, received: Instant) -> NodeValues {
+        let meters = values.map(|v| v.map(|p| self.depth.meters(p)));
+        let ttc = self.approach.update(&meters, received);
+        if let Some(t) = ttc.iter().flatten().copied().reduce(f32::min) {
+            Span::current().record("ttc_s", t);
+        }
+        self.approach.boost(values, &ttc)
+    }
+
+    fn drop_off_seen(&mut self, hit: Option<DropOffHit>) {
+        if self.drop_off.update(hit, Instant::now()) {
+            Metrics::inc(&self.metrics.drop_off_events);
+            if let Some(hit) = hit {
+                warn!(distance_m = hit.distance_m, fraction = hit.fraction, missing = hit.missing, "Drop-off ahead");
+            }
+        }
+    }
+
+    fn check_stop(&mut self, nearest: Option<f32>) {
+        let (mut status, changed) = self.stop.update(nearest);
+        status.drop_m = self.drop_off.hit(Instant::now()).map(|hit| hit.distance_m);
+        if let Some(m) = nearest {
+            Span::current().record("min_m", m);
+        }
+        self.stops.send_replace(status);
+        if !changed {
+            return;
+        }
+        if status.stop {
+            Metrics::inc(&self.metrics.stop_events);
+            warn!(min_m = nearest, "Stop: obstacle in the danger zone");
+        } else {
+            info!(min_m = nearest, "Stop cleared");
+        }
+    }
+
+    /// Drops a raised stop or drop-off when something other than a grid takes over the belt.
+    fn clear_stop(&mut self) {
+        let dropped = self.drop_off.reset(Instant::now());
+        if self.stop.reset() | dropped {
+            self.stops.send_replace(StopStatus::default());
+            info!("Stop cleared");
+        }
+    }
+
+    async fn emit(&self, seq: u64, format: FrameFormat, states: &[u8; NODE_COUNT], received: Instant) {
+        let span = Span::current();
+        span.record("states", field::debug(states));
+
+        let t = Instant::now();
+        let ok = self.forward(states).instrument(info_span!("serial")).await;
+        span.record("serial_us", t.elapsed().as_micros() as u64);
+
+        if ok {
+            let latency = received.elapsed();
+            self.metrics.ble_to_serial_latency.observe(latency);
+            info!(total_us = latency.as_micros() as u64, "Frame forwarded");
+        }
+        journal_frame(&self.journal, seq, format.as_str(), states);
+    }
+
+    async fn forward(&self, states: &[u8; NODE_COUNT]) -> bool {
+        let res = write_sink(&self.sink, &self.output(states));
+        if let Err(e) = &res {
+            Metrics::inc(&self.metrics.serial_write_errors);
+            error!("UART write failed: {e:?}");
+        }
+        let mut st = self.state.lock().await;
+        st.serial_ok = res.is_ok();
+        if res.is_ok() {
+            st.push_states(states, Instant::now());
+        }
+        res.is_ok()
+    }
+}
+
+fn frame_span(seq: u64, len: usize) -> Span {
+    info_span!(
+        "frame",
+        seq,
+        len,
+        format = field::Empty,
+        units = field::Empty,
+        grid = field::Empty,
+        min_m = field::Empty,
+        ttc_s = field::Empty,
+        states = field::Empty,
+        parse_us = field::Empty,
+        map_us = field::Empty,
+        serial_us = field::Empty,
+    )
+}
+
+fn journal_frame(journal: &Option<Journal>, seq: u64, format: &str, states: &[u8]) {
+    let Some(journal) = journal else {
+        return;
+    };
+    let states = states.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(",");
+    let _ = journal.send(
+        6,
+        &format!("Frame {seq} ({format}) -> [{states}]"),
+        &[
+            ("WHV_SEQ", seq.to_string()),
+            ("WHV_FORMAT", format.to_string()),
+            ("WHV_NODE_STATES", states),
+        ],
+    );
+}
+
+fn write_sink(sink: &Sink, bytes: &[u8]) -> io::Result<()> {
+    let mut guard = sink.lock().map_err(|_| io::Error::other("serial mutex poisoned"))?;
+    guard.write_all(bytes)?;
+    guard.flush()?;
+    Ok(())
+}
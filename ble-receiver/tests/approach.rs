@@ -0,0 +1,79 @@
+//! Approach speed, time-to-collision and the boost it gives.
+
+use ble_receiver::approach::{ApproachConfig, ApproachTracker};
+use std::time::{Duration, Instant};
+
+const STILL: Option<f32> = Some(4.0);
+
+fn tracker(smoothing: f32) -> ApproachTracker {
+    ApproachTracker::new(ApproachConfig {
+        smoothing,
+        min_speed_mps: 0.1,
+        ..Default::default()
+    })
+}
+
+fn nodes(first: Option<f32>) -> [Option<f32>; 6] {
+    [first, STILL, STILL, STILL, STILL, STILL]
+}
+
+fn close(a: Option<f32>, b: f32) -> bool {
+    a.is_some_and(|a| (a - b).abs() < 1e-4)
+}
+
+#[test]
+fn steady_approach_gives_time_to_collision() {
+    let mut t = tracker(1.0);
+    let t0 = Instant::now();
+    let ms = |n| t0 + Duration::from_millis(n);
+
+    assert_eq!(t.update(&nodes(Some(3.0)), t0), [None; 6]);
+    let ttc = t.update(&nodes(Some(2.75)), ms(250));
+    assert_eq!(ttc[0], Some(2.75));
+    assert_eq!(ttc[1..], [None; 5]);
+    assert_eq!(t.speeds()[0], 1.0);
+}
+
+#[test]
+fn speed_is_smoothed() {
+    let mut t = tracker(0.5);
+    let t0 = Instant::now();
+    let ms = |n| t0 + Duration::from_millis(n);
+
+    t.update(&nodes(Some(3.0)), t0);
+    assert!(close(t.update(&nodes(Some(2.75)), ms(250))[0], 5.5));
+    assert!(close(t.update(&nodes(Some(2.5)), ms(500))[0], 2.5 / 0.75));
+}
+
+#[test]
+fn gaps_and_unknown_nodes_restart_the_estimate() {
+    let mut t = tracker(1.0);
+    let t0 = Instant::now();
+    let ms = |n| t0 + Duration::from_millis(n);
+
+    t.update(&nodes(Some(3.0)), t0);
+    // Too long since the last frame.
+    assert_eq!(t.update(&nodes(Some(2.0)), ms(1000))[0], None);
+    // A burst frame right after is measured against the older reference.
+    assert_eq!(t.update(&nodes(Some(1.9)), ms(1005))[0], None);
+    assert!(close(t.update(&nodes(Some(1.75)), ms(1250))[0], 1.75));
+    // Losing the node forgets its speed.
+    assert_eq!(t.update(&nodes(None), ms(1500))[0], None);
+    assert_eq!(t.update(&nodes(Some(1.5)), ms(1750))[0], None);
+}
+
+#[test]
+fn only_nodes_inside_the_horizon_are_boosted() {
+    let t = tracker(1.0);
+    let values = [Some(0.5), Some(0.5), Some(0.9), None, Some(0.2), Some(0.2)];
+    let ttc = [Some(1.5), Some(3.0), Some(0.0), Some(0.5), None, Some(10.0)];
+
+    let boosted = t.boost(&values, &ttc);
+    assert_eq!(boosted, [Some(0.75), Some(0.5), Some(1.0), None, Some(0.2), Some(0.2)]);
+
+    let off = ApproachTracker::new(ApproachConfig {
+        enabled: false,
+        ..Default::default()
+    });
+    assert_eq!(off.boost(&values, &ttc), values);
+}
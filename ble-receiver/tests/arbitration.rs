@@ -0,0 +1,72 @@
+//! Control of the belt: one owner at a time, changed only by takeover or release.
+
+use ble_receiver::arbitration::{Arbiter, WriteVerdict};
+use bluer::Address;
+use std::time::{Duration, Instant};
+
+const PHONE: Address = Address::new([0x02, 0, 0, 0, 0, 0x01]);
+const OTHER_PHONE: Address = Address::new([0x02, 0, 0, 0, 0, 0x02]);
+
+#[test]
+fn the_first_writer_owns_the_belt() {
+    let mut arbiter = Arbiter::default();
+    let now = Instant::now();
+    assert_eq!(arbiter.owner(), None);
+    assert_eq!(arbiter.check_write(PHONE, now), WriteVerdict::Accepted);
+    assert_eq!(arbiter.check_write(PHONE, now), WriteVerdict::Accepted);
+    assert_eq!(arbiter.owner(), Some(PHONE));
+    assert_eq!(arbiter.centrals()[&PHONE].frames, 2);
+}
+
+#[test]
+fn others_are_rejected_however_quiet_the_owner_is() {
+    let mut arbiter = Arbiter::default();
+    let now = Instant::now();
+    arbiter.check_write(PHONE, now);
+
+    let later = now + Duration::from_secs(60);
+    assert_eq!(arbiter.check_write(OTHER_PHONE, later), WriteVerdict::Rejected { owner: PHONE });
+    assert_eq!(arbiter.owner(), Some(PHONE));
+    assert_eq!(arbiter.centrals()[&OTHER_PHONE].rejected, 1);
+    assert_eq!(arbiter.centrals()[&OTHER_PHONE].frames, 0);
+}
+
+#[test]
+fn takeover_moves_control_explicitly() {
+    let mut arbiter = Arbiter::default();
+    let now = Instant::now();
+    arbiter.check_write(PHONE, now);
+
+    assert_eq!(arbiter.takeover(OTHER_PHONE, now), Some(PHONE));
+    assert_eq!(arbiter.check_write(OTHER_PHONE, now), WriteVerdict::Accepted);
+    assert_eq!(arbiter.check_write(PHONE, now), WriteVerdict::Rejected { owner: OTHER_PHONE });
+
+    // Taking over from yourself reports no previous owner.
+    assert_eq!(arbiter.takeover(OTHER_PHONE, now), None);
+}
+
+#[test]
+fn release_frees_the_belt_for_the_next_writer() {
+    let mut arbiter = Arbiter::default();
+    let now = Instant::now();
+    arbiter.check_write(PHONE, now);
+
+    assert!(!arbiter.release(OTHER_PHONE));
+    assert_eq!(arbiter.owner(), Some(PHONE));
+    assert!(arbiter.release(PHONE));
+    assert_eq!(arbiter.owner(), None);
+    assert_eq!(arbiter.check_write(OTHER_PHONE, now), WriteVerdict::Accepted);
+    assert_eq!(arbiter.owner(), Some(OTHER_PHONE));
+}
+
+#[test]
+fn commands_follow_the_owner_without_counting_as_frames() {
+    let mut arbiter = Arbiter::default();
+    let now = Instant::now();
+    assert_eq!(arbiter.check_command(PHONE, now), WriteVerdict::Accepted);
+    assert_eq!(arbiter.owner(), Some(PHONE));
+    assert_eq!(arbiter.centrals()[&PHONE].frames, 0);
+
+    assert_eq!(arbiter.check_command(OTHER_PHONE, now), WriteVerdict::Rejected { owner: PHONE });
+    assert_eq!(arbiter.centrals()[&OTHER_PHONE].rejected, 1);
+}
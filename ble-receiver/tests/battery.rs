@@ -0,0 +1,41 @@
+//! Battery levels as the Battery Level characteristic reports them.
+
+use ble_receiver::{battery::BatterySource, standard_services::battery_level_value};
+use bluer::gatt::local::ReqError;
+use std::{fs, path::PathBuf};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("whv-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn fixed_levels_are_read_as_one_byte() {
+    let source: BatterySource = serde_json::from_str(r#"{"fixed": 80}"#).unwrap();
+    assert_eq!(source, BatterySource::Fixed(80));
+    assert_eq!(battery_level_value(&source).unwrap(), [80]);
+    assert_eq!(battery_level_value(&BatterySource::Fixed(0)).unwrap(), [0]);
+    assert_eq!(battery_level_value(&BatterySource::Fixed(250)).unwrap(), [100]);
+}
+
+#[test]
+fn file_levels_are_clamped_to_a_percentage() {
+    let path = temp_path("battery");
+    for (text, level) in [("57\n", 57), ("-5", 0), ("140", 100)] {
+        fs::write(&path, text).unwrap();
+        assert_eq!(battery_level_value(&BatterySource::File(path.clone())).unwrap(), [level]);
+    }
+
+    fs::write(&path, "full").unwrap();
+    assert!(matches!(battery_level_value(&BatterySource::File(path.clone())), Err(ReqError::Failed)));
+    fs::remove_file(&path).unwrap();
+    assert!(battery_level_value(&BatterySource::File(path)).is_err());
+}
+
+#[test]
+fn sysfs_levels_come_from_capacity() {
+    let dir = temp_path("power_supply");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("capacity"), "42\n").unwrap();
+    assert_eq!(battery_level_value(&BatterySource::Sysfs(dir.clone())).unwrap(), [42]);
+    fs::remove_dir_all(&dir).unwrap();
+}
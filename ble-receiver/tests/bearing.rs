@@ -0,0 +1,131 @@
+//! Nodes by bearing around the waist: headings, memory of sectors out of view and calibration.
+
+use ble_receiver::{
+    bearing::{Bearings, SectorMap},
+    frame::{parse_json_grid, View},
+    mapping::{CellPolicy, GridMapper, GridPolicy},
+};
+use std::time::{Duration, Instant};
+
+fn view(yaw_deg: f32) -> View {
+    View {
+        yaw_deg: Some(yaw_deg),
+        fov_deg: Some(60.0),
+    }
+}
+
+fn grid(top: [f32; 3]) -> Vec<Vec<f32>> {
+    vec![top.to_vec(), vec![0.0; 3]]
+}
+
+#[test]
+fn sectors_matching_the_columns_keep_the_column_mapping() {
+    let mut map = SectorMap::new(Bearings {
+        enabled: true,
+        node_bearing_deg: [-20.0, 0.0, 20.0, -20.0, 0.0, 20.0],
+        sector_width_deg: 20.0,
+        ..Default::default()
+    });
+    let cells = vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]];
+    assert_eq!(
+        map.node_cells(&cells, &View::default(), Instant::now()),
+        [0.1, 0.2, 0.3, 0.4, 0.5, 0.6].map(Some)
+    );
+}
+
+#[test]
+fn turning_the_phone_moves_obstacles_to_other_nodes() {
+    let mut map = SectorMap::new(Bearings {
+        enabled: true,
+        ..Default::default()
+    });
+    let t0 = Instant::now();
+
+    // The first heading is straight ahead; the phone then turns 40 degrees right.
+    map.node_cells(&grid([0.2; 3]), &view(10.0), t0);
+    let cells = map.node_cells(&grid([0.1, 0.9, 0.1]), &view(50.0), t0 + Duration::from_millis(100));
+    assert_eq!(cells[..3], [Some(0.2), Some(0.2), Some(0.9)]);
+
+    // The left sector was only seen in the first frame and is forgotten after a while.
+    let later = t0 + Duration::from_millis(1700);
+    let cells = map.node_cells(&grid([0.1, 0.9, 0.1]), &view(50.0), later);
+    assert_eq!(cells[..3], [None, Some(0.1), Some(0.9)]);
+}
+
+#[test]
+fn frames_facing_apart_cover_the_whole_waist() {
+    let mut map = SectorMap::new(Bearings {
+        enabled: true,
+        node_bearing_deg: [-120.0, 0.0, 120.0, -120.0, 0.0, 120.0],
+        sector_width_deg: 120.0,
+        fov_deg: 180.0,
+        ..Default::default()
+    });
+    let now = Instant::now();
+    let wide = |yaw| View {
+        yaw_deg: Some(yaw),
+        fov_deg: None,
+    };
+
+    map.node_cells(&grid([0.5; 3]), &wide(0.0), now);
+    let cells = map.node_cells(&grid([0.8; 3]), &wide(180.0), now);
+    assert_eq!(cells[..3], [Some(0.8), Some(0.5), Some(0.8)]);
+}
+
+#[test]
+fn nodes_out_of_view_follow_the_outside_grid_policy() {
+    let mut map = SectorMap::new(Bearings {
+        enabled: true,
+        node_bearing_deg: [-90.0, 0.0, 90.0, -90.0, 0.0, 90.0],
+        sector_width_deg: 20.0,
+        ..Default::default()
+    });
+    let frame = grid([0.7; 3]);
+    let cells = map.node_cells(&frame, &View::default(), Instant::now());
+    assert_eq!(cells, [None, Some(0.7), None, None, Some(0.0), None]);
+
+    // Far by default: nothing there, and not an invalid cell.
+    let mut mapper = GridMapper::new(GridPolicy::default());
+    let mapped = mapper.map_with(&frame, |idx| cells[idx]).unwrap();
+    assert_eq!(
+        mapped.values,
+        [Some(0.0), Some(0.7), Some(0.0), Some(0.0), Some(0.0), Some(0.0)]
+    );
+    assert_eq!(mapped.invalid, 0);
+
+    let mut mapper = GridMapper::new(GridPolicy {
+        outside_grid: CellPolicy::Unknown,
+        ..Default::default()
+    });
+    let mapped = mapper.map_with(&frame, |idx| cells[idx]).unwrap();
+    assert_eq!(mapped.values, [None, Some(0.7), None, None, Some(0.0), None]);
+}
+
+#[test]
+fn calibration_takes_the_next_heading_as_straight_ahead() {
+    let mut map = SectorMap::new(Bearings {
+        enabled: true,
+        yaw_offset_deg: 5.0,
+        ..Default::default()
+    });
+    assert_eq!(map.heading(&view(90.0)), 5.0);
+    assert_eq!(map.heading(&view(120.0)), 35.0);
+    assert_eq!(map.heading(&view(-90.0)), -175.0);
+
+    map.calibrate();
+    assert_eq!(map.heading(&view(-90.0)), 5.0);
+    assert_eq!(map.heading(&View::default()), 5.0);
+}
+
+#[test]
+fn json_grids_can_carry_a_heading() {
+    let grid = parse_json_grid(br#"{"grid": [[0.5]], "yaw_deg": -30, "fov_deg": 70}"#).unwrap();
+    assert_eq!(
+        grid.view,
+        View {
+            yaw_deg: Some(-30.0),
+            fov_deg: Some(70.0),
+        }
+    );
+    assert_eq!(parse_json_grid(b"[[0.5]]").unwrap().view, View::default());
+}
@@ -0,0 +1,102 @@
+//! Per-node corrections and output remapping, from config and the control characteristic.
+
+use ble_receiver::{
+    calibration::{Calibration, CalibrationError, NodeCalibration},
+    control::{parse_command, Command},
+};
+
+#[test]
+fn the_default_table_changes_nothing() {
+    let states = [1, 2, 3, 4, 3, 2];
+    assert_eq!(Calibration::default().apply(&states), states);
+}
+
+#[test]
+fn levels_are_corrected_per_node() {
+    let node = |json: &str| {
+        let calibration: NodeCalibration = serde_json::from_str(json).unwrap();
+        (1..=4).map(|s| calibration.apply(s)).collect::<Vec<_>>()
+    };
+
+    assert_eq!(node(r#"{"max_level": 2}"#), [2, 2, 3, 4]);
+    assert_eq!(node(r#"{"offset": 1}"#), [1, 1, 2, 3]);
+    assert_eq!(node(r#"{"offset": -1, "min_level": 1}"#), [2, 3, 3, 3]);
+    assert_eq!(node(r#"{"gain": 0.5}"#), [2, 3, 3, 4]);
+    assert_eq!(node(r#"{"disabled": true, "offset": 3}"#), [4; 4]);
+}
+
+#[test]
+fn nodes_can_be_moved_to_other_outputs() {
+    let table: Calibration =
+        serde_json::from_str(r#"[{"output": 2}, {}, {"output": 0}, {"output": 4}, {"disabled": true}, {}]"#).unwrap();
+    assert_eq!(table.validate(), Ok(()));
+
+    // Output 3 is driven by no node and stays empty; node 4 is disabled, so node 3 may take its output.
+    assert_eq!(table.apply(&[1, 2, 3, 2, 1, 3]), [3, 2, 1, 4, 2, 3]);
+}
+
+#[test]
+fn inverted_outputs_are_flipped_whatever_drives_them() {
+    let table: Calibration =
+        serde_json::from_str(r#"[{"invert": true, "output": 1}, {"invert": true, "max_level": 2}, {}, {}, {}, {}]"#)
+            .unwrap();
+    assert_eq!(
+        table.validate(),
+        Err(CalibrationError::SharedOutput {
+            output: 1,
+            nodes: [0, 1]
+        })
+    );
+
+    let table: Calibration = serde_json::from_str(
+        r#"[{"invert": true, "output": 2}, {"invert": true, "disabled": true}, {"output": 0}, {}, {}, {}]"#,
+    )
+    .unwrap();
+    // Output 0 is inverted and driven by node 2; output 1 belongs to a disabled node; output 2
+    // takes node 0's state as it is, since its own entry is not inverted.
+    assert_eq!(table.apply(&[1, 2, 3, 4, 4, 4]), [2, 1, 1, 4, 4, 4]);
+    assert_eq!(table.apply(&[4; 6]), [1, 1, 4, 4, 4, 4]);
+}
+
+#[test]
+fn tables_with_missing_or_shared_outputs_are_refused() {
+    let table: Calibration = serde_json::from_str(r#"[{"output": 6}, {}, {}, {}, {}, {}]"#).unwrap();
+    assert_eq!(
+        table.validate(),
+        Err(CalibrationError::NoSuchOutput { node: 0, output: 6 })
+    );
+    assert_eq!(
+        table.validate().unwrap_err().to_string(),
+        "node 0 drives output 6, outputs are 0..6"
+    );
+
+    let table: Calibration = serde_json::from_str(r#"[{}, {}, {}, {"output": 5}, {}, {}]"#).unwrap();
+    assert_eq!(
+        table.validate(),
+        Err(CalibrationError::SharedOutput {
+            output: 5,
+            nodes: [3, 5]
+        })
+    );
+
+    let path = std::env::temp_dir().join(format!("whv-{}-shared-output.json", std::process::id()));
+    std::fs::write(&path, r#"{"calibration": [{}, {}, {}, {"output": 5}, {}, {}]}"#).unwrap();
+    let err = ble_receiver::config::Config::load(&path).unwrap_err();
+    assert_eq!(err.to_string(), "calibration: nodes 3 and 5 both drive output 5");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn single_nodes_can_be_recalibrated_over_ble() {
+    assert_eq!(
+        parse_command(br#"{"cmd":"node_calibration","node":2,"max_level":2,"invert":true}"#),
+        Some(Command::NodeCalibration {
+            node: 2,
+            calibration: NodeCalibration {
+                max_level: 2,
+                invert: true,
+                ..Default::default()
+            },
+        })
+    );
+}
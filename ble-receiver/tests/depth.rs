@@ -0,0 +1,221 @@
+//! Decodes `server.py`-style depth messages and bins them into grids.
+
+use ble_receiver::{
+    depth::{self, Binning, DepthError, DepthFrame, DepthHeader, GroundFilter, Reduce},
+    dropoff::DropOff,
+};
+
+const IDENTITY: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+fn png16(width: u32, height: u32, px: impl Fn(u32, u32) -> u16) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut enc = png::Encoder::new(&mut out, width, height);
+    enc.set_color(png::ColorType::Grayscale);
+    enc.set_depth(png::BitDepth::Sixteen);
+    let mut w = enc.write_header().unwrap();
+    let data: Vec<u8> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| px(x, y).to_be_bytes())
+        .collect();
+    w.write_image_data(&data).unwrap();
+    drop(w);
+    out
+}
+
+fn message(header: &str, png: &[u8]) -> Vec<u8> {
+    let mut msg = (header.len() as u32).to_le_bytes().to_vec();
+    msg.extend_from_slice(header.as_bytes());
+    msg.extend_from_slice(png);
+    msg
+}
+
+fn header(scale: f32) -> String {
+    format!(
+        r#"{{"fx": 200.0, "fy": 200.0, "cx": 3.0, "cy": 2.0, "scale_m_per_unit": {scale}, "pose_4x4_row_major": {IDENTITY:?}}}"#
+    )
+}
+
+#[test]
+fn decodes_header_and_scales_pixels() {
+    // 6x4 image in half-metre units; left half 1 m, right half 3 m, one dropped-out pixel.
+    let png = png16(6, 4, |x, y| match (x, y) {
+        (0, 0) => 0,
+        (x, _) if x < 3 => 2,
+        _ => 6,
+    });
+    let frame = depth::decode(&message(&header(0.5), &png)).unwrap();
+
+    assert_eq!((frame.width, frame.height), (6, 4));
+    assert_eq!(frame.header.fx, 200.0);
+    assert_eq!(frame.header.pose_4x4_row_major, IDENTITY);
+    assert!(frame.at(0, 0).is_nan());
+    assert_eq!(frame.at(1, 0), 1.0);
+    assert_eq!(frame.at(5, 3), 3.0);
+}
+
+#[test]
+fn bins_with_each_reducer() {
+    // 1 m at the top-left, +1/8 m per column and +1 m per row.
+    let png = png16(6, 4, |x, y| (8 + x + 8 * y) as u16);
+    let frame = depth::decode(&message(&header(0.125), &png)).unwrap();
+
+    let bin = |reduce| {
+        Binning {
+            rows: 2,
+            cols: 3,
+            reduce,
+            min_valid_fraction: 0.0,
+        }
+        .apply(&frame)
+    };
+    assert_eq!(bin(Reduce::Min), [[1.0, 1.25, 1.5], [3.0, 3.25, 3.5]]);
+    assert_eq!(bin(Reduce::Mean), [[1.5625, 1.8125, 2.0625], [3.5625, 3.8125, 4.0625]]);
+    assert_eq!(bin(Reduce::Median)[0][0], 2.0);
+}
+
+#[test]
+fn sparse_bins_are_no_data() {
+    let png = png16(4, 2, |x, _| if x == 0 { 1 } else { 0 });
+    let frame = depth::decode(&message(&header(0.5), &png)).unwrap();
+    let grid = Binning {
+        rows: 1,
+        cols: 2,
+        reduce: Reduce::Min,
+        min_valid_fraction: 0.5,
+    }
+    .apply(&frame);
+
+    assert_eq!(grid[0][0], 0.5);
+    assert!(grid[0][1].is_nan());
+}
+
+#[test]
+fn rejects_bad_messages() {
+    let png = png16(2, 2, |_, _| 1000);
+    let good = message(&header(0.001), &png);
+
+    assert!(matches!(depth::decode(&good[..3]), Err(DepthError::Truncated)));
+    assert!(matches!(depth::decode(&good[..20]), Err(DepthError::Truncated)));
+    assert!(matches!(depth::decode(&message(r#"{"fx": 1.0}"#, &png)), Err(DepthError::Header(_))));
+    assert!(matches!(depth::decode(&message(&header(0.001), b"not a png")), Err(DepthError::Png(_))));
+
+    let mut rgb = Vec::new();
+    let mut enc = png::Encoder::new(&mut rgb, 1, 1);
+    enc.set_color(png::ColorType::Rgb);
+    enc.write_header().unwrap().write_image_data(&[0, 0, 0]).unwrap();
+    assert!(matches!(depth::decode(&message(&header(0.001), &rgb)), Err(DepthError::Image(_))));
+}
+
+/// A level phone 1.3 m up; rows below `cy` can see the floor.
+fn level_frame((width, height): (usize, usize), f: f32, cy: f32, depth: impl Fn(usize, usize) -> f32) -> DepthFrame {
+    let mut pose = IDENTITY;
+    pose[7] = 1.3;
+    DepthFrame {
+        header: DepthHeader {
+            fx: f,
+            fy: f,
+            cx: (width as f32 - 1.0) / 2.0,
+            cy,
+            scale_m_per_unit: 0.001,
+            pose_4x4_row_major: pose,
+        },
+        width,
+        height,
+        meters: (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| depth(x, y)).collect(),
+    }
+}
+
+#[test]
+fn floor_and_overhead_points_are_removed() {
+    // Rows 3.. hit the floor at 1.3 m below the camera; rows ..3 hit a wall 2 m away.
+    let mut frame = level_frame((8, 6), 4.0, 2.5, |_, y| if y < 3 { 2.0 } else { 1.3 * 4.0 / (y as f32 - 2.5) });
+    assert!(frame.world_point(0, 5).unwrap()[1].abs() < 1e-4);
+
+    let floor = GroundFilter::default().apply(&mut frame).unwrap();
+    assert!(floor.abs() < 1e-4);
+    for x in 0..8 {
+        // The wall at 2.55 m and 2.05 m is above head height; at 1.55 m it is an obstacle.
+        assert!(frame.at(x, 0).is_nan() && frame.at(x, 1).is_nan());
+        assert_eq!(frame.at(x, 2), 2.0);
+        assert!((3..6).all(|y| frame.at(x, y).is_nan()));
+    }
+}
+
+#[test]
+fn obstacles_stay_when_no_floor_is_in_view() {
+    // A narrow view of the wall only; its lowest point is far too close to the camera to be floor.
+    let mut frame = level_frame((8, 6), 40.0, 2.5, |_, _| 2.0);
+    let ground = GroundFilter::default();
+    assert_eq!(ground.floor_y(&frame), 1.3 - ground.camera_height_m);
+
+    ground.apply(&mut frame);
+    assert!(frame.meters.iter().all(|m| *m == 2.0));
+}
+
+#[test]
+fn zero_focal_length_is_not_a_floor() {
+    // Every ray is infinite, so no pixel has a height; found by the depth_frame fuzz target.
+    let frame = level_frame((8, 6), 0.0, 2.5, |_, _| 2.0);
+    let ground = GroundFilter::default();
+    assert_eq!(ground.floor_y(&frame), 1.3 - ground.camera_height_m);
+}
+
+/// Depth at which row `y` of a 24-row frame meets ground `drop` metres below the floor.
+fn ground(y: usize, drop: f32) -> f32 {
+    (1.3 + drop) * 8.0 / (y as f32 - 3.5)
+}
+
+#[test]
+fn flat_floor_is_not_a_drop_off() {
+    let frame = level_frame((8, 24), 8.0, 3.5, |_, y| if y < 4 { f32::NAN } else { ground(y, 0.0) });
+    let floor = GroundFilter::default().floor_y(&frame);
+    assert!(floor.abs() < 1e-3);
+    assert_eq!(DropOff::default().check(&frame, floor), None);
+}
+
+#[test]
+fn stairs_down_are_drop_offs() {
+    // From 1.1 m ahead the ground is 0.5 m lower; beyond 2 m nothing counts.
+    let frame = level_frame((8, 24), 8.0, 3.5, |_, y| match y {
+        0..=8 => f32::NAN,
+        9..=13 => ground(y, 0.5),
+        _ => ground(y, 0.0),
+    });
+    let floor = GroundFilter::default().floor_y(&frame);
+    assert!(floor.abs() < 1e-3, "floor {floor}");
+
+    let hit = DropOff::default().check(&frame, floor).expect("drop-off");
+    assert!((hit.fraction - 5.0 / 15.0).abs() < 1e-4, "{hit:?}");
+    assert_eq!(hit.missing, 0.0);
+    assert!((1.0..1.2).contains(&hit.distance_m), "{hit:?}");
+}
+
+#[test]
+fn missing_depth_needs_more_of_the_floor_than_a_step() {
+    // Five of the fifteen floor rows read nothing: a dark patch as much as a hole.
+    let patch = level_frame((8, 24), 8.0, 3.5, |_, y| if y < 14 { f32::NAN } else { ground(y, 0.0) });
+    let floor = GroundFilter::default().floor_y(&patch);
+    assert_eq!(DropOff::default().check(&patch, floor), None);
+
+    // Ten of them, from 0.7 m ahead on: a hole.
+    let hole = level_frame((8, 24), 8.0, 3.5, |_, y| if y < 19 { f32::NAN } else { ground(y, 0.0) });
+    let hit = DropOff::default().check(&hole, floor).expect("drop-off");
+    assert_eq!(hit.fraction, 0.0);
+    assert!((hit.missing - 10.0 / 15.0).abs() < 1e-4, "{hit:?}");
+    assert!((0.7..0.75).contains(&hit.distance_m), "{hit:?}");
+}
+
+#[test]
+fn no_floor_in_view_is_not_a_drop_off() {
+    // A narrow view sees the floor only beyond the lookahead, so no depth at all is no hazard.
+    let frame = level_frame((8, 24), 200.0, 3.5, |_, _| f32::NAN);
+    assert_eq!(DropOff::default().check(&frame, 0.0), None);
+
+    // The same with one row of floor in range, too few pixels to judge.
+    let frame = level_frame((8, 24), 8.0, 3.5, |_, _| f32::NAN);
+    let few = DropOff {
+        lookahead_m: 0.55,
+        ..Default::default()
+    };
+    assert_eq!(few.check(&frame, 0.0), None);
+}
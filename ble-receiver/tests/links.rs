@@ -0,0 +1,80 @@
+//! Connection bookkeeping and when a dropped link sends the belt to its safe state.
+
+use ble_receiver::{
+    connection::LinkEvent,
+    state::{AppState, LinkDown},
+};
+use bluer::Address;
+use std::time::Instant;
+
+const PHONE: Address = Address::new([0x02, 0, 0, 0, 0, 0x01]);
+const OTHER_PHONE: Address = Address::new([0x02, 0, 0, 0, 0, 0x02]);
+
+#[test]
+fn links_count_connects_and_disconnects() {
+    let mut st = AppState::default();
+    let now = Instant::now();
+    for evt in [
+        LinkEvent::Connected(PHONE),
+        LinkEvent::Connected(PHONE),
+        LinkEvent::Rssi(PHONE, -60),
+        LinkEvent::Mtu(PHONE, 185),
+        LinkEvent::Disconnected(PHONE),
+        LinkEvent::Connected(PHONE),
+    ] {
+        st.link_event(evt, now);
+    }
+
+    let link = st.links.get(&PHONE).unwrap();
+    assert_eq!((link.connects, link.disconnects), (2, 1));
+    assert_eq!((link.rssi, link.mtu), (Some(-60), Some(185)));
+    assert_eq!(st.links.connected_count(), 1);
+}
+
+#[test]
+fn the_owner_disconnecting_drives_the_safe_state() {
+    let mut st = AppState::default();
+    let now = Instant::now();
+    st.link_event(LinkEvent::Connected(PHONE), now);
+    st.link_event(LinkEvent::Connected(OTHER_PHONE), now);
+    st.arbiter.check_write(PHONE, now);
+
+    assert_eq!(
+        st.link_event(LinkEvent::Disconnected(PHONE), now),
+        Some(LinkDown {
+            was_owner: true,
+            safe_state: true,
+        })
+    );
+    assert_eq!(st.arbiter.owner(), None);
+
+    // A second report of the same disconnect changes nothing.
+    assert_eq!(st.link_event(LinkEvent::Disconnected(PHONE), now), None);
+}
+
+#[test]
+fn the_last_central_disconnecting_drives_the_safe_state() {
+    let mut st = AppState::default();
+    let now = Instant::now();
+    st.link_event(LinkEvent::Connected(PHONE), now);
+    st.link_event(LinkEvent::Connected(OTHER_PHONE), now);
+    st.arbiter.check_write(PHONE, now);
+
+    // A bystander leaving while the owner stays is not a reason to stop.
+    assert_eq!(
+        st.link_event(LinkEvent::Disconnected(OTHER_PHONE), now),
+        Some(LinkDown {
+            was_owner: false,
+            safe_state: false,
+        })
+    );
+
+    st.arbiter.release(PHONE);
+    assert_eq!(
+        st.link_event(LinkEvent::Disconnected(PHONE), now),
+        Some(LinkDown {
+            was_owner: false,
+            safe_state: true,
+        })
+    );
+}
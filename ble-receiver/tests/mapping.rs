@@ -0,0 +1,62 @@
+//! Cell policies: which cells count as invalid, what a node holds, and short grids.
+
+use ble_receiver::mapping::{CellPolicy, GridMapper, GridPolicy};
+
+fn mapper(non_finite: CellPolicy, out_of_range: CellPolicy) -> GridMapper {
+    GridMapper::new(GridPolicy {
+        non_finite,
+        out_of_range,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn short_grids_leave_the_rest_of_the_belt_far() {
+    let mut m = GridMapper::new(GridPolicy::default());
+    let mapped = m.map(&[vec![0.9, 0.3, 0.0]]).unwrap();
+    assert_eq!(
+        mapped.values,
+        [Some(0.9), Some(0.3), Some(0.0), Some(0.0), Some(0.0), Some(0.0)]
+    );
+    assert_eq!(mapped.invalid, 0);
+    assert!(m.unknown_pulse(&mapped.values).is_none());
+
+    let mut m = GridMapper::new(GridPolicy {
+        outside_grid: CellPolicy::Unknown,
+        ..Default::default()
+    });
+    let mapped = m.map(&[vec![0.9], vec![0.3]]).unwrap();
+    assert_eq!(mapped.values, [Some(0.9), None, None, Some(0.3), None, None]);
+}
+
+#[test]
+fn only_cells_left_unknown_count_as_invalid() {
+    let grid = [vec![2.0, -1.0, f32::NAN], vec![0.5, f32::INFINITY, 0.5]];
+
+    let mapped = mapper(CellPolicy::Clamp, CellPolicy::Clamp).map(&grid).unwrap();
+    assert_eq!(mapped.invalid, 0);
+    assert_eq!(
+        mapped.values,
+        [Some(1.0), Some(0.0), Some(0.0), Some(0.5), Some(1.0), Some(0.5)]
+    );
+
+    let mapped = mapper(CellPolicy::Unknown, CellPolicy::Far).map(&grid).unwrap();
+    assert_eq!(mapped.invalid, 2);
+    assert_eq!(mapped.values, [Some(0.0), Some(0.0), None, Some(0.5), None, Some(0.5)]);
+}
+
+#[test]
+fn hold_keeps_only_values_that_were_in_range() {
+    let mut m = mapper(CellPolicy::Hold, CellPolicy::Clamp);
+    let first = m.map(&[vec![f32::NAN, 0.4, 0.2]]).unwrap();
+    assert_eq!(first.values[..3], [None, Some(0.4), Some(0.2)]);
+    assert_eq!(first.invalid, 1, "nothing held yet");
+
+    // A clamped 5.0 is sent as 1.0 but is not what node 1 holds.
+    let second = m.map(&[vec![0.7, 5.0, 0.2]]).unwrap();
+    assert_eq!(second.values[..3], [Some(0.7), Some(1.0), Some(0.2)]);
+
+    let third = m.map(&[vec![f32::NAN, f32::NAN, 0.2]]).unwrap();
+    assert_eq!(third.values[..3], [Some(0.7), Some(0.4), Some(0.2)]);
+    assert_eq!(third.invalid, 0);
+}
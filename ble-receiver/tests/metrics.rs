@@ -0,0 +1,73 @@
+//! The Prometheus text the endpoint serves, and where it may listen.
+
+use ble_receiver::metrics::{self, FrameFormat, Metrics, ParseFailure};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[test]
+fn counters_render_with_their_types() {
+    let m = Metrics::default();
+    m.frame(FrameFormat::Json);
+    m.frame(FrameFormat::Json);
+    m.parse_failure(ParseFailure::Length);
+    Metrics::inc(&m.stop_events);
+    m.ble_to_serial_latency.observe(Duration::from_millis(3));
+
+    let text = m.render();
+    for line in [
+        "# TYPE whv_frames_total counter",
+        "whv_frames_total{format=\"json\"} 2",
+        "whv_frames_total{format=\"raw\"} 0",
+        "# TYPE whv_parse_failures_total counter",
+        "whv_parse_failures_total{kind=\"length\"} 1",
+        "# TYPE whv_stop_events_total counter",
+        "whv_stop_events_total 1",
+        "# TYPE whv_watchdog_trips_total counter",
+        "# TYPE whv_ble_to_serial_latency_seconds histogram",
+        "whv_ble_to_serial_latency_seconds_bucket{le=\"0.0025\"} 0",
+        "whv_ble_to_serial_latency_seconds_bucket{le=\"0.005\"} 1",
+        "whv_ble_to_serial_latency_seconds_count 1",
+    ] {
+        assert!(text.lines().any(|l| l == line), "missing {line:?} in\n{text}");
+    }
+    // Every sample follows the HELP and TYPE lines of its family.
+    assert_eq!(text.matches("# HELP ").count(), text.matches("# TYPE ").count());
+}
+
+#[tokio::test]
+async fn endpoint_answers_over_http() {
+    let m = Arc::new(Metrics::default());
+    m.frame(FrameFormat::Raw);
+    let listener = metrics::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(metrics::serve_on(listener, Arc::clone(&m)));
+
+    let get = |path: &'static str| async move {
+        let mut sock = TcpStream::connect(addr).await.unwrap();
+        sock.write_all(format!("GET {path} HTTP/1.1\r\nHost: x\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut resp = String::new();
+        sock.read_to_string(&mut resp).await.unwrap();
+        resp
+    };
+
+    let resp = get("/metrics").await;
+    let (head, body) = resp.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK"));
+    assert!(head.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    assert!(body.contains("whv_frames_total{format=\"raw\"} 1"));
+
+    assert!(get("/other").await.starts_with("HTTP/1.1 404 Not Found"));
+}
+
+#[tokio::test]
+async fn only_loopback_addresses_are_served() {
+    let err = metrics::bind("0.0.0.0:0").await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(metrics::bind("localhost:0").await.is_ok());
+}
@@ -0,0 +1,150 @@
+//! Effects over the live node states: stepping, priorities, blending and one-shots.
+
+use ble_receiver::pattern::{Pattern, PatternEngine, PatternError, PatternLibrary, Slot, MAX_STEP};
+use std::time::{Duration, Instant};
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn effects_blend_with_the_live_frame() {
+    let t0 = Instant::now();
+    let mut engine = PatternEngine::new([4; 6]);
+    engine.set_live([1, 2, 3, 4, 3, 2]);
+    engine.play(Slot::Unknown, Pattern::pulse(&[1, 4], [2, 4], 100), t0);
+
+    assert_eq!(engine.render(t0), [1, 2, 3, 4, 2, 2]);
+    assert_eq!(engine.render(t0 + ms(150)), [1, 4, 3, 4, 4, 2]);
+    assert_eq!(engine.render(t0 + ms(250)), [1, 2, 3, 4, 2, 2]);
+    assert_eq!(engine.next_change(t0 + ms(250)), Some(t0 + ms(300)));
+
+    assert!(engine.stop(Slot::Unknown));
+    assert_eq!(engine.render(t0 + ms(300)), [1, 2, 3, 4, 3, 2]);
+    assert_eq!(engine.next_change(t0 + ms(300)), None);
+}
+
+#[test]
+fn higher_slots_win_whatever_the_order_they_start_in() {
+    let t0 = Instant::now();
+    let mut engine = PatternEngine::new([4; 6]);
+    engine.play(
+        Slot::Stop,
+        Pattern::double_tap(&[0, 1, 2, 3, 4, 5], [1, 4], 100, 300),
+        t0,
+    );
+    engine.play(Slot::Cue, Pattern::sweep(&[0, 1, 2], 2, 100), t0);
+
+    assert_eq!(engine.render(t0), [1; 6]);
+    assert_eq!(engine.render(t0 + ms(100)), [4; 6]);
+
+    engine.stop(Slot::Stop);
+    assert_eq!(engine.render(t0 + ms(100)), [4, 2, 4, 4, 4, 4]);
+}
+
+#[test]
+fn one_shots_end_and_repeats_wrap() {
+    let t0 = Instant::now();
+    let sweep = Pattern::sweep(&[3, 4, 5], 1, 50);
+    assert_eq!(sweep.duration(), ms(150));
+
+    let mut engine = PatternEngine::new([4; 6]);
+    engine.play(Slot::Cue, sweep, t0);
+    assert_eq!(engine.render(t0 + ms(120)), [4, 4, 4, 4, 4, 1]);
+    assert_eq!(engine.render(t0 + ms(150)), [4; 6]);
+    assert!(!engine.is_playing(Slot::Cue));
+
+    engine.play(Slot::Cue, Pattern::throb(&[0], 10), t0);
+    let levels: Vec<u8> = (0..8).map(|k| engine.render(t0 + ms(20 * k))[0]).collect();
+    assert_eq!(levels, [4, 3, 2, 1, 2, 3, 4, 3]);
+}
+
+#[test]
+fn ensure_keeps_the_rhythm_of_an_effect_already_playing() {
+    let t0 = Instant::now();
+    let mut engine = PatternEngine::new([4; 6]);
+    let pulse = Pattern::pulse(&[0], [1, 3], 100);
+    engine.ensure(Slot::Unknown, pulse.clone(), t0);
+    engine.ensure(Slot::Unknown, pulse, t0 + ms(100));
+    assert_eq!(engine.render(t0 + ms(100))[0], 3);
+
+    engine.ensure(Slot::Unknown, Pattern::pulse(&[0, 1], [1, 3], 100), t0 + ms(100));
+    assert_eq!(engine.render(t0 + ms(100))[..2], [1, 1]);
+}
+
+#[test]
+fn patterns_can_be_read_from_config() {
+    let pattern: Pattern = serde_json::from_str(
+        r#"{"steps": [{"states": [1, null, null, null, null, 1], "ms": 200}, {"states": [null, null, null, null, null, null], "ms": 5}]}"#,
+    )
+    .unwrap();
+    assert_eq!(pattern.repeat, 1);
+    assert_eq!(pattern.duration(), ms(220));
+    assert_eq!(pattern.validate(), Err(PatternError::TooShort { step: 1, ms: 5 }));
+
+    let mut engine = PatternEngine::new([3; 6]);
+    let t0 = Instant::now();
+    engine.play(Slot::Cue, pattern, t0);
+    assert_eq!(engine.render(t0), [1, 3, 3, 3, 3, 1]);
+}
+
+#[test]
+fn pattern_files_are_checked_when_loaded() {
+    let library = PatternLibrary::parse(
+        br#"{
+            "turn_left": {"repeat": 2, "steps": [{"states": [0, 0, 1, 0, 0, 1], "ms": 100}, {"states": [1, 0, 0, 1, 0, 0], "ms": 100}]},
+            "urgent": {"priority": 25, "repeat": 0, "steps": [{"states": [1, 1, 1, 1, 1, 1], "ms": 100}]}
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(library.names().collect::<Vec<_>>(), ["turn_left", "urgent"]);
+
+    let t0 = Instant::now();
+    let mut engine = PatternEngine::new([4; 6]);
+    engine.play(Slot::Cue, library.get("turn_left").unwrap().clone(), t0);
+    assert_eq!(engine.render(t0 + ms(200)), [4, 4, 1, 4, 4, 1]);
+    assert_eq!(engine.render(t0 + ms(400)), [4; 6]);
+
+    // A pattern's own priority beats the slot it plays in.
+    engine.play(Slot::Cue, Pattern::pulse(&[0], [3, 3], 100), t0);
+    engine.play(Slot::Unknown, library.get("urgent").unwrap().clone(), t0);
+    assert_eq!(engine.render(t0)[0], 1);
+
+    let err = PatternLibrary::parse(br#"{"bad": {"steps": [{"states": [1, 5, 0, 0, 0, 0], "ms": 100}]}}"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "pattern `bad`: step 0: node 1 has state 5, expected 0..=4"
+    );
+    assert!(PatternLibrary::parse(br#"{"empty": {"steps": []}}"#).is_err());
+    assert!(PatternLibrary::parse(br#"{"short": {"steps": [{"states": [1, 1, 1], "ms": 100}]}}"#).is_err());
+}
+
+#[test]
+fn pattern_files_cannot_outrank_alerts_or_run_unbounded() {
+    let step = r#"[{"states": [1, 1, 1, 1, 1, 1], "ms": 100}]"#;
+    let err = |json: String| PatternLibrary::parse(json.as_bytes()).unwrap_err().to_string();
+
+    assert_eq!(
+        err(format!(r#"{{"loud": {{"priority": 30, "steps": {step}}}}}"#)),
+        "pattern `loud`: priority 30 must be below 30"
+    );
+    assert_eq!(
+        err(format!(r#"{{"long": {{"repeat": 4294967295, "steps": {step}}}}}"#)),
+        "pattern `long`: repeats 4294967295 times, at most 1000 allowed"
+    );
+    assert_eq!(
+        err(r#"{"slow": {"steps": [{"states": [1, 1, 1, 1, 1, 1], "ms": 18446744073709551615}]}}"#.to_string()),
+        "pattern `slow`: step 0 lasts 18446744073709551615 ms, at most 60000 allowed"
+    );
+}
+
+#[test]
+fn huge_built_in_patterns_do_not_overflow() {
+    let t0 = Instant::now();
+    let pattern = Pattern::frames(&[[1; 6], [2; 6]], u64::MAX, u32::MAX);
+
+    let mut engine = PatternEngine::new([4; 6]);
+    engine.play(Slot::Cue, pattern, t0);
+    assert_eq!(engine.render(t0 + ms(1000)), [1; 6]);
+    assert_eq!(engine.next_change(t0), Some(t0 + MAX_STEP));
+}
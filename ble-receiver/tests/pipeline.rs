@@ -0,0 +1,564 @@
+//! Drives the write handler and worker end to end without a BLE adapter or a Feather.
+
+use ble_receiver::{
+    approach::ApproachTracker,
+    bearing::SectorMap,
+    calibration::{Calibration, NodeCalibration},
+    control::{parse_command, Command},
+    danger::{DangerZone, StopDetector, StopStatus},
+    dropoff::{DropOff, DropOffAlert, DropOffHit},
+    frame::{Grid, Units},
+    mapping::{DepthRange, GridMapper, GridPolicy},
+    metrics::{FrameFormat, Metrics},
+    pattern::{PatternEngine, PatternLibrary},
+    profile::Profile,
+    sim::{PtySim, SimParams},
+    state::AppState,
+    validation::{OutOfRange, RawPolicy},
+    worker::{handle_command, handle_write, spawn_worker, Sink, Worker, WorkerMsg},
+};
+use bluer::{gatt::local::ReqError, Address};
+use std::{
+    io::{self, Write},
+    sync::{atomic::Ordering, Arc, Mutex as StdMutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, oneshot, watch, Mutex},
+    task::JoinHandle,
+};
+
+const PHONE: Address = Address::new([0x02, 0, 0, 0, 0, 0x01]);
+const OTHER_PHONE: Address = Address::new([0x02, 0, 0, 0, 0, 0x02]);
+const SAFE: [u8; 6] = [4; 6];
+/// A long cue on the left column, for playing by name.
+const LIBRARY: &[u8] = br#"{"nudge": {"steps": [{"states": [1, 0, 0, 1, 0, 0], "ms": 60000}]}}"#;
+
+/// In-memory stand-in for the serial port.
+#[derive(Clone, Default)]
+struct Capture(Arc<StdMutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Harness {
+    state: Arc<Mutex<AppState>>,
+    tx: mpsc::UnboundedSender<WorkerMsg>,
+    metrics: Arc<Metrics>,
+    stops: watch::Receiver<StopStatus>,
+    worker: JoinHandle<()>,
+}
+
+impl Harness {
+    fn new(sink: Box<dyn Write + Send>) -> Self {
+        Self::with_policy(sink, RawPolicy::default())
+    }
+
+    fn with_policy(sink: Box<dyn Write + Send>, raw_policy: RawPolicy) -> Self {
+        let sink: Sink = Arc::new(StdMutex::new(sink));
+        let state = Arc::new(Mutex::new(AppState::default()));
+        let metrics = Arc::new(Metrics::default());
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (stops_tx, stops) = watch::channel(StopStatus::default());
+        let library = PatternLibrary::parse(LIBRARY).unwrap();
+        let worker = spawn_worker(
+            rx,
+            Worker {
+                state: Arc::clone(&state),
+                sink,
+                safe_state: SAFE,
+                raw_policy,
+                mapper: GridMapper::new(GridPolicy {
+                    unknown_blink_ms: 60_000,
+                    ..Default::default()
+                }),
+                depth: DepthRange::default(),
+                sectors: SectorMap::new(Default::default()),
+                approach: ApproachTracker::new(Default::default()),
+                stop: StopDetector::new(DangerZone {
+                    alert_blink_ms: 60_000,
+                    ..Default::default()
+                }),
+                drop_off: DropOffAlert::new(DropOff {
+                    alert_blink_ms: 60_000,
+                    ..Default::default()
+                }),
+                patterns: PatternEngine::new(SAFE),
+                library,
+                profile: Profile::default(),
+                calibration: Calibration::default(),
+                stops: stops_tx,
+                journal: None,
+                metrics: Arc::clone(&metrics),
+            },
+        );
+        Self {
+            state,
+            tx,
+            metrics,
+            stops,
+            worker,
+        }
+    }
+
+    async fn write(&self, from: Address, data: &[u8]) -> Result<(), ReqError> {
+        handle_write(&self.state, &self.tx, from, data.to_vec()).await
+    }
+
+    /// Returns once the worker has handled everything queued before the call.
+    async fn settle(&self) {
+        let (pong_tx, pong_rx) = oneshot::channel();
+        self.tx.send(WorkerMsg::Ping(pong_tx)).unwrap();
+        pong_rx.await.unwrap();
+    }
+
+    async fn shutdown(self) -> io::Result<()> {
+        self.state.lock().await.shutting_down = true;
+        let (done_tx, done_rx) = oneshot::channel();
+        self.tx.send(WorkerMsg::Shutdown(done_tx)).unwrap();
+        let res = done_rx.await.unwrap();
+        self.worker.await.unwrap();
+        res
+    }
+}
+
+async fn run(payloads: &[&[u8]]) -> (Vec<u8>, Arc<Metrics>) {
+    run_with(RawPolicy::default(), payloads).await
+}
+
+async fn run_with(policy: RawPolicy, payloads: &[&[u8]]) -> (Vec<u8>, Arc<Metrics>) {
+    let out = Capture::default();
+    let h = Harness::with_policy(Box::new(out.clone()), policy);
+    for p in payloads {
+        h.write(PHONE, p).await.unwrap();
+    }
+    h.settle().await;
+    let metrics = Arc::clone(&h.metrics);
+    let bytes = out.0.lock().unwrap().clone();
+    (bytes, metrics)
+}
+
+#[tokio::test]
+async fn json_array_is_mapped_to_node_states() {
+    let (out, m) = run(&[b"[[1.0, 0.8, 0.6], [0.4, 0.2, 0.0]]"]).await;
+    assert_eq!(out, [1, 1, 2, 3, 4, 4]);
+    assert_eq!(m.frames_json.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn grid_object_is_mapped_to_node_states() {
+    let (out, _) = run(&[br#"{"grid": [[0.5, 0.5, 0.5], [0.9, 0.1, 0.3]]}"#]).await;
+    assert_eq!(out, [2, 2, 2, 1, 4, 3]);
+}
+
+#[tokio::test]
+async fn metric_grids_are_scaled_on_the_pi() {
+    let (out, _) = run(&[br#"{"grid": [[0.3, 5.0, 2.0], [0.1, 9.0, null]], "units": "meters"}"#]).await;
+    assert_eq!(out, [1, 4, 2, 1, 4, 2]);
+}
+
+#[tokio::test]
+async fn binary_grids_carry_units() {
+    let cell = |v: u16| v.to_le_bytes();
+    let mut proximity = b"WG\x00\x02\x03".to_vec();
+    let mut meters = b"WG\x01\x02\x03".to_vec();
+    for v in [10_000, 0, 5_000, 8_000, 2_000, 0xFFFF] {
+        proximity.extend(cell(v));
+    }
+    for v in [300, 5_000, 2_000, 100, 9_000, 0xFFFF] {
+        meters.extend(cell(v));
+    }
+
+    let (out, m) = run(&[&proximity, &meters, b"WG\x02\x01\x01\x00\x00", b"WG\x00\x02\x03\x00"]).await;
+    assert_eq!(out, [1, 4, 2, 1, 4, 2, 1, 4, 2, 1, 4, 2]);
+    assert_eq!(m.frames_binary.load(Ordering::Relaxed), 2);
+    assert_eq!(m.parse_failures_binary.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn raw_frames_pass_through() {
+    let (out, m) = run(&[&[1, 2, 3, 4, 1, 2], &[4, 3, 2, 1, 4, 3]]).await;
+    assert_eq!(out, [1, 2, 3, 4, 1, 2, 4, 3, 2, 1, 4, 3]);
+    assert_eq!(m.frames_raw.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn raw_frames_of_the_wrong_length_are_dropped() {
+    let (out, m) = run(&[&[1, 2, 3], &[], &[4, 3, 2, 1, 4, 3, 0xFF, 0xFF]]).await;
+    assert!(out.is_empty());
+    assert_eq!(m.parse_failures_length.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn out_of_range_raw_frames_are_rejected_by_default() {
+    let (out, m) = run(&[&[1, 2, 0, 4, 255, 2], &[1, 1, 1, 1, 1, 5]]).await;
+    assert!(out.is_empty());
+    assert_eq!(m.parse_failures_out_of_range.load(Ordering::Relaxed), 2);
+    assert_eq!(m.raw_state_violations.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn out_of_range_raw_frames_can_be_clamped() {
+    let policy = RawPolicy {
+        levels: 3,
+        out_of_range: OutOfRange::Clamp,
+    };
+    let (out, m) = run_with(policy, &[&[1, 2, 0, 4, 255, 3]]).await;
+    assert_eq!(out, [1, 2, 1, 3, 3, 3]);
+    assert_eq!(m.raw_state_violations.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn malformed_json_is_dropped() {
+    let (out, m) = run(&[b"[[1.0, 0.5", b"[[1.0], [0.1, 0.2]]", br#"{"grid": "near"}"#, b"[]", b"[[]]"]).await;
+    assert!(out.is_empty());
+    assert_eq!(m.parse_failures_json.load(Ordering::Relaxed), 5);
+}
+
+#[tokio::test]
+async fn missing_cells_pulse_instead_of_reading_as_far() {
+    // 1e39 overflows f32 to infinity; null is "no data"; the 1x3 grid leaves the bottom row far.
+    let (out, m) = run(&[b"[[1.0, null, 1e39], [0.0, 0.0, 0.0]]", b"[[0.0, 0.0, 0.0]]"]).await;
+    assert_eq!(out, [1, 2, 2, 4, 4, 4, 4, 4, 4, 4, 4, 4]);
+    assert_eq!(m.invalid_cells.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn mostly_invalid_grids_are_rejected() {
+    let (out, m) = run(&[b"[[null, null, 0.5], [null, null, 2.0]]"]).await;
+    assert!(out.is_empty());
+    assert_eq!(m.parse_failures_grid.load(Ordering::Relaxed), 1);
+}
+
+fn metric_grid(centre: f32) -> Vec<u8> {
+    format!(r#"{{"grid": [[2.0, {centre}, 2.0], [2.0, 2.0, 2.0]], "units": "meters"}}"#).into_bytes()
+}
+
+#[tokio::test]
+async fn obstacles_ahead_raise_a_stop_alert() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+
+    // Only the centre column is in the danger zone; 0.5 m off to the side does not stop.
+    h.write(PHONE, br#"{"grid": [[0.5, 2.0, 2.0], [2.0, 2.0, 2.0]], "units": "meters"}"#)
+        .await
+        .unwrap();
+    h.write(PHONE, &metric_grid(0.6)).await.unwrap();
+    h.settle().await;
+    let status = *h.stops.borrow();
+    assert_eq!(
+        status,
+        StopStatus {
+            stop: true,
+            min_m: Some(0.6),
+            drop_m: None
+        }
+    );
+    assert_eq!(status.to_json(), r#"{"type":"cmd","stop":true,"min_m":0.60,"drop_off":false}"#);
+
+    // 0.85 m is inside the hysteresis band, so the alert keeps tapping until 1 m.
+    h.write(PHONE, &metric_grid(0.85)).await.unwrap();
+    h.write(PHONE, &metric_grid(1.0)).await.unwrap();
+    h.settle().await;
+
+    assert_eq!(
+        *out.0.lock().unwrap(),
+        [[1, 2, 2, 2, 2, 2], [1; 6], [1; 6], [2, 1, 2, 2, 2, 2]].concat()
+    );
+    assert_eq!(
+        *h.stops.borrow(),
+        StopStatus {
+            stop: false,
+            min_m: Some(1.0),
+            drop_m: None
+        }
+    );
+    assert_eq!(h.metrics.stop_events.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn proximity_grids_do_not_stop_by_default() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    h.write(PHONE, b"[[0.0, 1.0, 0.0], [0.0, 0.0, 0.0]]").await.unwrap();
+    h.settle().await;
+
+    assert_eq!(*out.0.lock().unwrap(), [4, 1, 4, 4, 4, 4]);
+    assert_eq!(*h.stops.borrow(), StopStatus::default());
+    assert_eq!(h.metrics.stop_events.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn raw_frames_end_a_stop() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    h.write(PHONE, &metric_grid(0.4)).await.unwrap();
+    h.write(PHONE, &[3; 6]).await.unwrap();
+    h.settle().await;
+
+    assert_eq!(*out.0.lock().unwrap(), [[1; 6], [3; 6]].concat());
+    assert!(!h.stops.borrow().stop);
+}
+
+#[tokio::test]
+async fn approaching_obstacles_are_boosted() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    let t0 = Instant::now();
+
+    // Node 0 closes from 3 m to 2.5 m in 100 ms; node 3 sits at 2.5 m throughout.
+    for (cells, after_ms) in [
+        [[3.0, 5.0, 5.0], [2.5, 5.0, 5.0]],
+        [[2.5, 5.0, 5.0], [2.5, 5.0, 5.0]],
+    ]
+    .into_iter()
+    .zip([0, 100])
+    {
+        let grid = Grid {
+            cells: cells.map(Vec::from).to_vec(),
+            units: Units::Meters,
+            ..Default::default()
+        };
+        h.tx.send(WorkerMsg::Grid {
+            format: FrameFormat::Json,
+            grid,
+            nearest_m: None,
+            drop_off: None,
+            received: t0 + Duration::from_millis(after_ms),
+        })
+        .unwrap();
+    }
+    h.settle().await;
+
+    assert_eq!(*out.0.lock().unwrap(), [[3, 4, 4, 2, 4, 4], [1, 4, 4, 2, 4, 4]].concat());
+}
+
+#[tokio::test]
+async fn drop_offs_get_their_own_alert() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    let far = || Grid {
+        cells: vec![vec![5.0; 3]; 2],
+        units: Units::Meters,
+        ..Default::default()
+    };
+    let hit = DropOffHit {
+        fraction: 0.6,
+        missing: 0.0,
+        distance_m: 1.2,
+    };
+
+    // The alert holds through a following frame that no longer sees the drop.
+    for drop_off in [Some(hit), None] {
+        h.tx.send(WorkerMsg::Grid {
+            format: FrameFormat::Depth,
+            grid: far(),
+            nearest_m: None,
+            drop_off,
+            received: Instant::now(),
+        })
+        .unwrap();
+    }
+    h.settle().await;
+
+    assert_eq!(*out.0.lock().unwrap(), [[1, 1, 1, 4, 4, 4]; 2].concat());
+    let status = *h.stops.borrow();
+    assert_eq!(status.drop_m, Some(1.2));
+    assert_eq!(status.to_json(), r#"{"type":"cmd","stop":false,"min_m":5.00,"drop_off":true,"drop_m":1.20}"#);
+    assert_eq!(h.metrics.drop_off_events.load(Ordering::Relaxed), 1);
+
+    // Raw frames take the belt back.
+    h.write(PHONE, &[3; 6]).await.unwrap();
+    h.settle().await;
+    assert_eq!(h.stops.borrow().drop_m, None);
+}
+
+#[tokio::test]
+async fn patterns_play_by_name_over_the_live_frame() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+
+    h.write(PHONE, &[2; 6]).await.unwrap();
+    h.tx.send(WorkerMsg::Play("nudge".into())).unwrap();
+    h.tx.send(WorkerMsg::Play("missing".into())).unwrap();
+    h.settle().await;
+    h.write(PHONE, &[3; 6]).await.unwrap();
+    h.tx.send(WorkerMsg::StopPattern).unwrap();
+    h.settle().await;
+
+    assert_eq!(
+        *out.0.lock().unwrap(),
+        [[2; 6], [1, 2, 2, 1, 2, 2], [1, 3, 3, 1, 3, 3], [3; 6]].concat()
+    );
+}
+
+#[tokio::test]
+async fn calibration_applies_to_the_belt_but_not_the_logs() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+
+    h.write(PHONE, &[1, 2, 3, 4, 1, 2]).await.unwrap();
+    let moved = NodeCalibration {
+        max_level: 1,
+        output: Some(3),
+        ..Default::default()
+    };
+    let disabled = NodeCalibration {
+        disabled: true,
+        ..Default::default()
+    };
+    // Output 3 is node 3's until it is disabled, so the first move is refused.
+    for (node, calibration) in [(0, moved), (3, disabled), (0, moved)] {
+        h.tx.send(WorkerMsg::NodeCalibration { node, calibration }).unwrap();
+    }
+    h.settle().await;
+
+    assert_eq!(
+        *out.0.lock().unwrap(),
+        [[1, 2, 3, 4, 1, 2], [1, 2, 3, 4, 1, 2], [4, 2, 3, 3, 1, 2]].concat()
+    );
+    assert_eq!(h.state.lock().await.last_states, Some([1, 2, 3, 4, 1, 2]));
+}
+
+#[tokio::test]
+async fn the_safe_state_empties_inverted_and_disabled_outputs() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+
+    h.write(PHONE, &[2; 6]).await.unwrap();
+    for (node, json) in [
+        (0, r#"{"invert": true}"#),
+        (1, r#"{"disabled": true, "invert": true}"#),
+        (2, r#"{"disabled": true}"#),
+    ] {
+        let calibration = serde_json::from_str(json).unwrap();
+        h.tx.send(WorkerMsg::NodeCalibration { node, calibration }).unwrap();
+    }
+    h.tx.send(WorkerMsg::SafeState).unwrap();
+    h.settle().await;
+    let sent = out.0.lock().unwrap().split_off(0);
+    assert_eq!(sent[sent.len() - 6..], [1, 1, 4, 4, 4, 4]);
+
+    // Inverted outputs are empty at 1, on shutdown too.
+    h.shutdown().await.unwrap();
+    assert_eq!(*out.0.lock().unwrap(), [1, 1, 4, 4, 4, 4]);
+}
+
+#[tokio::test]
+async fn switching_profiles_retunes_the_belt() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+
+    h.write(PHONE, br#"{"grid": [[0.3, 0.6, 0.9], [0.3, 0.6, 0.9]]}"#).await.unwrap();
+    h.tx.send(WorkerMsg::Profile(Profile {
+        thresholds: [0.2, 0.4, 0.6],
+        max_level: [3, 3, 2, 3, 3, 3],
+        ..Default::default()
+    }))
+    .unwrap();
+    h.write(PHONE, br#"{"grid": [[0.3, 0.6, 0.9], [0.3, 0.6, 0.9]]}"#).await.unwrap();
+    h.settle().await;
+
+    // The switch resends the last frame with the new limits, then the next grid maps with the
+    // new thresholds.
+    assert_eq!(
+        *out.0.lock().unwrap(),
+        [[3, 2, 1, 3, 2, 1], [3, 2, 2, 3, 2, 1], [3, 1, 2, 3, 1, 1]].concat()
+    );
+}
+
+#[tokio::test]
+async fn writes_from_a_second_central_are_refused() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    h.write(PHONE, &[1; 6]).await.unwrap();
+    assert!(matches!(h.write(OTHER_PHONE, &[2; 6]).await, Err(ReqError::NotPermitted)));
+    h.settle().await;
+    assert_eq!(*out.0.lock().unwrap(), [1; 6]);
+}
+
+#[tokio::test]
+async fn only_the_owner_may_send_commands() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    h.write(PHONE, &[1; 6]).await.unwrap();
+
+    let disable = parse_command(br#"{"cmd":"node_calibration","node":0,"disabled":true}"#).unwrap();
+    assert!(matches!(
+        handle_command(&h.state, &h.tx, OTHER_PHONE, disable.clone()).await,
+        Err(ReqError::NotAuthorized)
+    ));
+    assert!(matches!(
+        handle_command(&h.state, &h.tx, OTHER_PHONE, Command::Play { name: "urgent".into() }).await,
+        Err(ReqError::NotAuthorized)
+    ));
+    h.settle().await;
+    assert_eq!(*out.0.lock().unwrap(), [1; 6]);
+
+    // Takeover is open to everyone, and then the new owner's commands go through.
+    handle_command(&h.state, &h.tx, OTHER_PHONE, Command::Takeover).await.unwrap();
+    handle_command(&h.state, &h.tx, OTHER_PHONE, disable).await.unwrap();
+    h.settle().await;
+    assert_eq!(*out.0.lock().unwrap(), [[1; 6], [4, 1, 1, 1, 1, 1]].concat());
+    assert_eq!(h.state.lock().await.arbiter.centrals()[&OTHER_PHONE].rejected, 2);
+}
+
+#[tokio::test]
+async fn only_an_explicit_takeover_moves_control() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    h.write(PHONE, &[1; 6]).await.unwrap();
+
+    let prev = h.state.lock().await.arbiter.takeover(OTHER_PHONE, Instant::now());
+    assert_eq!(prev, Some(PHONE));
+    h.write(OTHER_PHONE, &[2; 6]).await.unwrap();
+    assert!(matches!(h.write(PHONE, &[3; 6]).await, Err(ReqError::NotPermitted)));
+    h.settle().await;
+    assert_eq!(*out.0.lock().unwrap(), [[1; 6], [2; 6]].concat());
+}
+
+#[tokio::test]
+async fn shutdown_drives_safe_state_and_refuses_writes() {
+    let out = Capture::default();
+    let h = Harness::new(Box::new(out.clone()));
+    h.write(PHONE, &[1; 6]).await.unwrap();
+    let state = Arc::clone(&h.state);
+    let tx = h.tx.clone();
+    h.shutdown().await.unwrap();
+
+    assert!(matches!(handle_write(&state, &tx, PHONE, vec![1; 6]).await, Err(ReqError::Failed)));
+    let out = out.0.lock().unwrap();
+    assert_eq!(out[out.len() - 6..], SAFE);
+}
+
+#[tokio::test]
+async fn belt_follows_frames_over_a_pty() {
+    let sim = PtySim::spawn(SimParams::default()).unwrap();
+    let port = serialport::new(sim.path().to_string_lossy(), 115_200)
+        .timeout(Duration::from_millis(200))
+        .open()
+        .unwrap();
+    let h = Harness::new(Box::new(port));
+
+    h.write(PHONE, b"[[1.0, 1.0, 1.0], [0.0, 0.0, 0.0]]").await.unwrap();
+    h.write(PHONE, &[1, 2, 3, 4, 1, 2]).await.unwrap();
+    h.settle().await;
+    assert!(sim.wait_for_frames(2, Duration::from_secs(2)));
+
+    let belt = sim.snapshot();
+    assert_eq!(belt.states, [1, 2, 3, 4, 1, 2]);
+    assert_eq!(belt.pins[1], (false, true));
+    assert_eq!(belt.pins[3], (true, true));
+
+    h.shutdown().await.unwrap();
+    assert!(sim.wait_for_frames(3, Duration::from_secs(2)));
+    assert_eq!(sim.snapshot().states, SAFE);
+}
@@ -0,0 +1,70 @@
+//! Wearer profiles: the mapping tunables they carry, node limits and the file they persist in.
+
+use ble_receiver::{
+    mapping::{GridMapper, GridPolicy},
+    profile::{Profile, ProfileStore},
+};
+use std::{fs, path::PathBuf};
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("whv-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn profiles_retune_the_mapping() {
+    let profile: Profile = serde_json::from_str(r#"{"thresholds": [0.1, 0.2, 0.3], "history": 2}"#).unwrap();
+    let mut grid = GridPolicy::default();
+    let mut depth = Default::default();
+    profile.tune(&mut grid, &mut depth);
+    let mut mapper = GridMapper::new(grid);
+
+    let first = mapper.map(&[vec![0.15, 0.25, 0.35], vec![0.0, f32::NAN, 0.5]]).unwrap();
+    assert_eq!(mapper.states(&first.values), [3, 2, 1, 4, 2, 1]);
+
+    // Each node averages the last two frames; an unknown node stays unknown.
+    let second = mapper.map(&[vec![0.35, 0.25, 0.15], vec![0.5, 0.5, f32::NAN]]).unwrap();
+    assert_eq!(
+        second.values,
+        [Some(0.25), Some(0.25), Some(0.25), Some(0.25), Some(0.5), None]
+    );
+}
+
+#[test]
+fn profiles_cap_and_disable_nodes() {
+    let profile = Profile {
+        enabled: [true, true, false, true, true, true],
+        max_level: [1, 3, 3, 2, 3, 0],
+        ..Default::default()
+    };
+    assert_eq!(profile.limit(&[1; 6]), [3, 1, 4, 2, 1, 4]);
+    assert_eq!(Profile::default().limit(&[1, 2, 3, 4, 1, 2]), [1, 2, 3, 4, 1, 2]);
+}
+
+#[test]
+fn the_active_profile_is_persisted() {
+    let path = temp_file("profiles.json");
+    fs::write(
+        &path,
+        r#"{"profiles": {"sam": {"max_level": [2, 2, 2, 2, 2, 2]}, "alex": {"history": 3}}}"#,
+    )
+    .unwrap();
+
+    let mut store = ProfileStore::load(&path).unwrap();
+    assert_eq!(store.active(), None);
+    assert_eq!(store.to_json(), r#"{"active":null,"profiles":["alex","sam"]}"#);
+    assert_eq!(store.select("nobody"), None);
+    assert_eq!(store.select("sam").map(|p| p.max_level), Some([2; 6]));
+    store.save().unwrap();
+
+    let store = ProfileStore::load(&path).unwrap();
+    assert_eq!(store.active_name(), Some("sam"));
+    assert_eq!(store.active().map(|p| p.history), Some(1));
+    assert_eq!(store.to_json(), r#"{"active":"sam","profiles":["alex","sam"]}"#);
+
+    fs::write(&path, r#"{"active": "gone", "profiles": {}}"#).unwrap();
+    assert!(ProfileStore::load(&path).is_err());
+    fs::remove_file(&path).unwrap();
+
+    // No file yet is an empty store.
+    assert_eq!(ProfileStore::load(&path).unwrap().names().count(), 0);
+}
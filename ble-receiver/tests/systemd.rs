@@ -0,0 +1,41 @@
+//! sd_notify datagrams and the watchdog period, against a socket standing in for systemd.
+
+use ble_receiver::systemd::{self, Notifier};
+use std::{os::unix::net::UnixDatagram, time::Duration};
+
+#[test]
+fn notifier_sends_the_lifecycle_states() {
+    let dir = std::env::temp_dir().join(format!("whv-{}-notify", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("notify.sock");
+    let _ = std::fs::remove_file(&path);
+    let manager = UnixDatagram::bind(&path).unwrap();
+    manager.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+
+    let notifier = Notifier::connect(path.to_str().unwrap()).unwrap();
+    notifier.ready().unwrap();
+    notifier.watchdog().unwrap();
+    notifier.stopping().unwrap();
+
+    let mut buf = [0u8; 64];
+    let received: Vec<String> = (0..3)
+        .map(|_| {
+            let n = manager.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        })
+        .collect();
+    assert_eq!(received, ["READY=1", "WATCHDOG=1", "STOPPING=1"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn watchdog_pings_at_a_third_of_the_period() {
+    std::env::remove_var("WATCHDOG_PID");
+    std::env::set_var("WATCHDOG_USEC", "3000000");
+    assert_eq!(systemd::watchdog_interval(), Some(Duration::from_secs(1)));
+
+    std::env::set_var("WATCHDOG_PID", (std::process::id() + 1).to_string());
+    assert_eq!(systemd::watchdog_interval(), None);
+    std::env::remove_var("WATCHDOG_PID");
+}